@@ -1,31 +1,203 @@
-use std::{env::current_dir, os::unix::fs::symlink, path::PathBuf};
+use std::{
+	env::current_dir,
+	io::{stdin, IsTerminal},
+	os::unix::fs::symlink,
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use clap::Parser;
+use dialoguer::{theme::ColorfulTheme, MultiSelect, Select};
 use fleet_base::{
-	deploy::{deploy_task, upload_task, DeployAction},
-	host::{Config, DeployKind, GenerationStorage},
+	deploy::{
+		deploy_task, get_current_generation, upload_task, DeployAction,
+		DEFAULT_ROLLBACK_CONFIRM_RETRY_INTERVAL, DEFAULT_ROLLBACK_CONFIRM_TIMEOUT,
+	},
+	host::{Config, ConfigHost, DeployKind, Generation, GenerationStorage},
 	opts::FleetOpts,
 };
 use nix_eval::{nix_go, NixBuildBatch};
-use tokio::task::LocalSet;
+use tabled::{Table, Tabled};
+use tokio::{sync::Semaphore, task::LocalSet};
 use tracing::{error, field, info, info_span, warn, Instrument};
 
+/// One host's outcome out of a fanned-out `BuildSystems`/`Deploy` run, reported into a shared
+/// [`HostOutcomes`] by the spawned task handling that host. `phase` is the last stage the host
+/// reached (e.g. "build"/"upload"/"activate"); `error` is `None` on success.
+struct HostOutcome {
+	host: String,
+	phase: &'static str,
+	error: Option<String>,
+}
+
+type HostOutcomes = Arc<Mutex<Vec<HostOutcome>>>;
+
+#[derive(Tabled)]
+struct HostOutcomeRow {
+	#[tabled(rename = "Host")]
+	host: String,
+	#[tabled(rename = "Phase")]
+	phase: String,
+	#[tabled(rename = "Status")]
+	status: String,
+}
+impl From<&HostOutcome> for HostOutcomeRow {
+	fn from(outcome: &HostOutcome) -> Self {
+		Self {
+			host: outcome.host.clone(),
+			phase: outcome.phase.to_owned(),
+			status: match &outcome.error {
+				None => "ok".to_owned(),
+				Some(e) => format!("failed: {e}"),
+			},
+		}
+	}
+}
+
+/// Prints a per-host summary table for `action` (e.g. "build"/"deploy") and fails the whole run
+/// if any host reported an error, so a CI pipeline driving `fleet deploy` sees a non-zero exit
+/// when even one host out of many fails.
+fn summarize_outcomes(action: &str, outcomes: HostOutcomes) -> Result<()> {
+	let outcomes = Arc::into_inner(outcomes)
+		.expect("all spawned tasks finished by the time set.await returns")
+		.into_inner()
+		.expect("outcomes mutex is never held across a panic");
+	let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+	let rows: Vec<HostOutcomeRow> = outcomes.iter().map(HostOutcomeRow::from).collect();
+	info!("{action} summary:\n{}", Table::new(rows));
+	if failed > 0 {
+		bail!("{failed}/{} host(s) failed to {action}", outcomes.len());
+	}
+	Ok(())
+}
+
 #[derive(Parser)]
 pub struct Deploy {
 	/// Disable automatic rollback
 	#[clap(long)]
 	disable_rollback: bool,
+	/// Preview the resolved deploy plan for every host and require explicit confirmation
+	/// (mirrors deploy-rs's interactive prompt) before building further than the toplevel closure
+	#[clap(long, visible_alias = "confirm")]
+	interactive: bool,
+	/// Limit how many hosts are uploaded/activated concurrently, instead of spawning every host
+	/// at once - a bad closure or an overloaded deployer otherwise risks taking down a whole
+	/// fleet in parallel
+	#[clap(long)]
+	bound: Option<usize>,
+	/// Deploy to these host(s) first; only proceed to the remaining hosts once every canary has
+	/// activated and confirmed it's still reachable. Aborts the whole rollout, without touching
+	/// any other host, if a canary fails.
+	#[clap(long, value_delimiter = ',')]
+	canary: Vec<String>,
 	/// Action to execute after system is built
 	action: DeployAction,
 }
 
+/// One host's resolved deploy plan, as built by the first phase of `Deploy::run`, shown to the
+/// operator by `--interactive` before any upload/activation happens.
+struct HostPlan {
+	host: ConfigHost,
+	deploy_kind: DeployKind,
+	built: PathBuf,
+	current_generation: Option<Generation>,
+	disable_rollback: bool,
+	rollback_confirm_timeout: Duration,
+	rollback_confirm_retry_interval: Duration,
+}
+
+#[derive(Tabled)]
+struct HostPlanRow {
+	#[tabled(rename = "Host")]
+	host: String,
+	#[tabled(rename = "Kind")]
+	kind: String,
+	#[tabled(rename = "Built")]
+	built: String,
+	#[tabled(rename = "Current Generation")]
+	current_generation: String,
+	#[tabled(rename = "Rollback")]
+	rollback: String,
+}
+impl HostPlanRow {
+	fn new(plan: &HostPlan, action: DeployAction) -> Self {
+		let rollback = if plan.disable_rollback {
+			if plan.deploy_kind != DeployKind::Fleet {
+				"DISABLED (unsupported by non-fleet deploy kind)".to_owned()
+			} else {
+				"disabled (--disable-rollback)".to_owned()
+			}
+		} else if action.should_create_rollback_marker() {
+			"armed".to_owned()
+		} else {
+			"n/a (upload only)".to_owned()
+		};
+		Self {
+			host: plan.host.name.clone(),
+			kind: format!("{:?}", plan.deploy_kind),
+			built: plan.built.display().to_string(),
+			current_generation: plan
+				.current_generation
+				.as_ref()
+				.map(|g| format!("{} ({})", g.id, g.datetime))
+				.unwrap_or_else(|| "-".to_owned()),
+			rollback,
+		}
+	}
+}
+
+/// Prints every host's resolved plan and asks the operator to proceed with all of them, pick a
+/// subset, or abort entirely; returns the plans to actually deploy, or `None` to abort. Always
+/// prompts and does not check for a terminal itself - callers should only reach here after
+/// checking `--interactive` was requested on an interactive terminal.
+fn confirm_plans(plans: Vec<HostPlan>, action: DeployAction) -> Result<Option<Vec<HostPlan>>> {
+	let rows: Vec<HostPlanRow> = plans.iter().map(|p| HostPlanRow::new(p, action)).collect();
+	info!("deploy plan:\n{}", Table::new(rows));
+
+	const PROCEED_ALL: &str = "Proceed with all hosts";
+	const SELECT_HOSTS: &str = "Select hosts individually";
+	const ABORT: &str = "Abort";
+	let choice = Select::with_theme(&ColorfulTheme::default())
+		.with_prompt("Proceed with this deploy?")
+		.items(&[PROCEED_ALL, SELECT_HOSTS, ABORT])
+		.default(0)
+		.interact()?;
+
+	match choice {
+		0 => Ok(Some(plans)),
+		1 => {
+			let names: Vec<String> = plans.iter().map(|p| p.host.name.clone()).collect();
+			let defaults = vec![true; names.len()];
+			let selected = MultiSelect::with_theme(&ColorfulTheme::default())
+				.with_prompt("Hosts to deploy (space to toggle, enter to confirm)")
+				.items(&names)
+				.defaults(&defaults)
+				.interact()?;
+			let selected: std::collections::HashSet<usize> = selected.into_iter().collect();
+			Ok(Some(
+				plans
+					.into_iter()
+					.enumerate()
+					.filter(|(i, _)| selected.contains(i))
+					.map(|(_, p)| p)
+					.collect(),
+			))
+		}
+		_ => Ok(None),
+	}
+}
+
 #[derive(Parser, Clone)]
 pub struct BuildSystems {
 	/// Attribute to build. Systems are deployed from "toplevel" attr, well-known used attributes
 	/// are "sdImage"/"isoImage", and your configuration may include any other build attributes.
 	#[clap(long, default_value = "toplevel")]
 	build_attr: String,
+	/// Limit how many hosts are built concurrently, instead of spawning every host at once
+	#[clap(long)]
+	bound: Option<usize>,
 }
 
 async fn build_task(
@@ -73,118 +245,341 @@ impl BuildSystems {
 				.nix_session
 				.new_build_batch("build-hosts".to_string())
 		});
+		let semaphore = self.bound.map(|n| Arc::new(Semaphore::new(n)));
+		let outcomes: HostOutcomes = Arc::new(Mutex::new(Vec::new()));
 		for host in hosts {
 			let config = config.clone();
 			let span = info_span!("build", host = field::display(&host.name));
 			let hostname = host.name;
 			let build_attr = build_attr.clone();
 			let batch = batch.clone();
+			let outcomes = outcomes.clone();
+			let semaphore = semaphore.clone();
 			set.spawn_local(
 				(async move {
+					let _permit = match &semaphore {
+						Some(semaphore) => Some(
+							semaphore
+								.acquire_owned()
+								.await
+								.expect("semaphore is never closed"),
+						),
+						None => None,
+					};
 					let built = match build_task(config, hostname.clone(), &build_attr, batch).await
 					{
 						Ok(path) => path,
 						Err(e) => {
 							error!("failed to deploy host: {}", e);
+							outcomes.lock().expect("not poisoned").push(HostOutcome {
+								host: hostname,
+								phase: "build",
+								error: Some(e.to_string()),
+							});
 							return;
 						}
 					};
-					// TODO: Handle error
 					let mut out = current_dir().expect("cwd exists");
 					out.push(format!("built-{}", hostname));
 
 					info!("linking iso image to {:?}", out);
-					if let Err(e) = symlink(built, out) {
-						error!("failed to symlink: {e}")
-					}
+					let error = if let Err(e) = symlink(built, out) {
+						error!("failed to symlink: {e}");
+						Some(e.to_string())
+					} else {
+						None
+					};
+					outcomes.lock().expect("not poisoned").push(HostOutcome {
+						host: hostname,
+						phase: "link",
+						error,
+					});
 				})
 				.instrument(span),
 			);
 		}
 		drop(batch);
 		set.await;
-		Ok(())
+		summarize_outcomes("build", outcomes)
 	}
 }
 
 impl Deploy {
 	pub async fn run(self, config: &Config, opts: &FleetOpts) -> Result<()> {
 		let hosts = opts.filter_skipped(config.list_hosts().await?).await?;
-		let set = LocalSet::new();
 		let batch = (hosts.len() > 1).then(|| {
 			config
 				.nix_session
 				.new_build_batch("deploy-hosts".to_string())
 		});
-		for host in hosts.into_iter() {
-			let config = config.clone();
-			let span = info_span!("deploy", host = field::display(&host.name));
-			let hostname = host.name.clone();
-			let opts = opts.clone();
-			let batch = batch.clone();
-			if let Some(deploy_kind) = opts.action_attr::<DeployKind>(&host, "deploy_kind").await? {
-				host.set_deploy_kind(deploy_kind);
-			};
+		let outcomes: HostOutcomes = Arc::new(Mutex::new(Vec::new()));
 
-			set.spawn_local(
-				(async move {
-					let built =
-						match build_task(config.clone(), hostname.clone(), "toplevel", batch).await
-						{
-							Ok(path) => path,
-							Err(e) => {
-								error!("failed to build host system closure: {}", e);
+		// Phase 1: build every host's toplevel closure and resolve its deploy plan concurrently,
+		// without touching the remote host yet - this is what `--interactive` previews.
+		let plans: Arc<Mutex<Vec<HostPlan>>> = Arc::new(Mutex::new(Vec::new()));
+		{
+			let set = LocalSet::new();
+			let semaphore = self.bound.map(|n| Arc::new(Semaphore::new(n)));
+			for host in hosts.into_iter() {
+				let config = config.clone();
+				let span = info_span!("plan", host = field::display(&host.name));
+				let hostname = host.name.clone();
+				let opts = opts.clone();
+				let batch = batch.clone();
+				let outcomes = outcomes.clone();
+				let plans = plans.clone();
+				let semaphore = semaphore.clone();
+				if let Some(deploy_kind) = opts.action_attr::<DeployKind>(&host, "deploy_kind").await? {
+					host.set_deploy_kind(deploy_kind);
+				};
+
+				set.spawn_local(
+					(async move {
+						let _permit = match &semaphore {
+							Some(semaphore) => Some(
+								semaphore
+									.acquire_owned()
+									.await
+									.expect("semaphore is never closed"),
+							),
+							None => None,
+						};
+						macro_rules! fail {
+							($phase:literal, $($arg:tt)*) => {{
+								error!($($arg)*);
+								outcomes.lock().expect("not poisoned").push(HostOutcome {
+									host: hostname,
+									phase: $phase,
+									error: Some(format!($($arg)*)),
+								});
 								return;
-							}
+							}};
+						}
+
+						let built =
+							match build_task(config.clone(), hostname.clone(), "toplevel", batch).await
+							{
+								Ok(path) => path,
+								Err(e) => fail!("build", "failed to build host system closure: {e}"),
+							};
+
+						let deploy_kind = match host.deploy_kind().await {
+							Ok(v) => v,
+							Err(e) => fail!("prepare", "failed to query target deploy kind: {e}"),
 						};
 
-					let deploy_kind = match host.deploy_kind().await {
-						Ok(v) => v,
-						Err(e) => {
-							error!("failed to query target deploy kind: {e}");
-							return;
+						// TODO: Make disable_rollback a host attribute instead
+						let mut disable_rollback = self.disable_rollback;
+						if !disable_rollback && deploy_kind != DeployKind::Fleet {
+							warn!("disabling rollback, as not supported by non-fleet deployment kinds");
+							disable_rollback = true;
 						}
+
+						let rollback_confirm_timeout = match opts
+							.action_attr::<u64>(&host, "rollback_confirm_timeout")
+							.await
+						{
+							Ok(v) => v.map(Duration::from_secs).unwrap_or(DEFAULT_ROLLBACK_CONFIRM_TIMEOUT),
+							Err(e) => fail!("prepare", "failed to read rollback_confirm_timeout: {e}"),
+						};
+						let rollback_confirm_retry_interval = match opts
+							.action_attr::<u64>(&host, "rollback_confirm_retry_interval")
+							.await
+						{
+							Ok(v) => v
+								.map(Duration::from_secs)
+								.unwrap_or(DEFAULT_ROLLBACK_CONFIRM_RETRY_INTERVAL),
+							Err(e) => fail!("prepare", "failed to read rollback_confirm_retry_interval: {e}"),
+						};
+
+						let current_generation = if !disable_rollback
+							&& self.action.should_create_rollback_marker()
+						{
+							match get_current_generation(&host).await {
+								Ok(g) => Some(g),
+								Err(e) => fail!("prepare", "failed to query current generation: {e}"),
+							}
+						} else {
+							None
+						};
+
+						plans.lock().expect("not poisoned").push(HostPlan {
+							host,
+							deploy_kind,
+							built,
+							current_generation,
+							disable_rollback,
+							rollback_confirm_timeout,
+							rollback_confirm_retry_interval,
+						});
+					})
+					.instrument(span),
+				);
+			}
+			drop(batch);
+			set.await;
+		}
+		let mut plans = Arc::into_inner(plans)
+			.expect("all spawned tasks finished by the time set.await returns")
+			.into_inner()
+			.expect("plans mutex is never held across a panic");
+		plans.sort_by(|a, b| a.host.name.cmp(&b.host.name));
+
+		if self.interactive {
+			ensure!(
+				stdin().is_terminal(),
+				"--interactive requires an interactive terminal"
+			);
+			match confirm_plans(plans, self.action)? {
+				Some(confirmed) => plans = confirmed,
+				None => bail!("deploy aborted by operator"),
+			}
+		}
+
+		// Phase 2: upload and activate the confirmed hosts, canaries first if `--canary` was given.
+		if self.canary.is_empty() {
+			self.deploy_phase(plans, config, opts, outcomes.clone()).await;
+		} else {
+			let canary_names: std::collections::HashSet<&str> =
+				self.canary.iter().map(String::as_str).collect();
+			let (canary_plans, remaining_plans): (Vec<_>, Vec<_>) = plans
+				.into_iter()
+				.partition(|p| canary_names.contains(p.host.name.as_str()));
+			if let Some(missing) = canary_names
+				.iter()
+				.find(|name| !canary_plans.iter().any(|p| &p.host.name == *name))
+			{
+				bail!("canary host {missing:?} is not part of this deploy");
+			}
+
+			info!(
+				"deploying {} canary host(s) before the rest of the fleet",
+				canary_plans.len()
+			);
+			self
+				.deploy_phase(canary_plans, config, opts, outcomes.clone())
+				.await;
+			let canary_failures: Vec<String> = outcomes
+				.lock()
+				.expect("not poisoned")
+				.iter()
+				.filter_map(|o| {
+					o.error
+						.as_ref()
+						.map(|e| format!("{}: {e}", o.host))
+				})
+				.collect();
+			if !canary_failures.is_empty() {
+				bail!(
+					"canary rollout failed, aborting before touching the remaining {} host(s): {}",
+					remaining_plans.len(),
+					canary_failures.join("; ")
+				);
+			}
+
+			info!(
+				"canaries are healthy, proceeding with the remaining {} host(s)",
+				remaining_plans.len()
+			);
+			self
+				.deploy_phase(remaining_plans, config, opts, outcomes.clone())
+				.await;
+		}
+		summarize_outcomes("deploy", outcomes)
+	}
+
+	/// Uploads and activates every plan in `plans` concurrently, bounded by `--bound` if set, and
+	/// records each host's outcome into `outcomes`. Called once for a plain deploy, or twice (once
+	/// per batch) for a `--canary` staged rollout.
+	async fn deploy_phase(
+		&self,
+		plans: Vec<HostPlan>,
+		config: &Config,
+		opts: &FleetOpts,
+		outcomes: HostOutcomes,
+	) {
+		let set = LocalSet::new();
+		let semaphore = self.bound.map(|n| Arc::new(Semaphore::new(n)));
+		for plan in plans {
+			let config = config.clone();
+			let span = info_span!("deploy", host = field::display(&plan.host.name));
+			let hostname = plan.host.name.clone();
+			let opts = opts.clone();
+			let outcomes = outcomes.clone();
+			let semaphore = semaphore.clone();
+			let action = self.action;
+
+			set.spawn_local(
+				(async move {
+					let _permit = match &semaphore {
+						Some(semaphore) => Some(
+							semaphore
+								.acquire_owned()
+								.await
+								.expect("semaphore is never closed"),
+						),
+						None => None,
 					};
+					let HostPlan {
+						host,
+						built,
+						disable_rollback,
+						rollback_confirm_timeout,
+						rollback_confirm_retry_interval,
+						..
+					} = plan;
 
-					// TODO: Make disable_rollback a host attribute instead
-					let mut disable_rollback = self.disable_rollback;
-					if !disable_rollback && deploy_kind != DeployKind::Fleet {
-						warn!("disabling rollback, as not supported by non-fleet deployment kinds");
-						disable_rollback = true;
+					macro_rules! fail {
+						($phase:literal, $($arg:tt)*) => {{
+							error!($($arg)*);
+							outcomes.lock().expect("not poisoned").push(HostOutcome {
+								host: hostname,
+								phase: $phase,
+								error: Some(format!($($arg)*)),
+							});
+							return;
+						}};
 					}
 
 					let remote_path =
 						match upload_task(&config, &host, GenerationStorage::Deployer, built).await
 						{
 							Ok(v) => v,
-							Err(e) => {
-								error!("upload failed: {e}");
-								return;
-							}
+							Err(e) => fail!("upload", "upload failed: {e}"),
 						};
 
-					if let Err(e) = deploy_task(
-						self.action,
+					let specialisation = match opts.action_attr(&host, "specialisation").await {
+						Ok(v) => v,
+						Err(_) => fail!("prepare", "unreachable? failed to get specialization"),
+					};
+
+					let error = if let Err(e) = deploy_task(
+						action,
 						&host,
 						remote_path,
-						if let Ok(v) = opts.action_attr(&host, "specialisation").await {
-							v
-						} else {
-							error!("unreachable? failed to get specialization");
-							return;
-						},
+						specialisation,
 						disable_rollback,
+						rollback_confirm_timeout,
+						rollback_confirm_retry_interval,
+						&config,
+						None,
 					)
 					.await
 					{
 						error!("activation failed: {e}");
-					}
+						Some(e.to_string())
+					} else {
+						None
+					};
+					outcomes.lock().expect("not poisoned").push(HostOutcome {
+						host: hostname,
+						phase: "activate",
+						error,
+					});
 				})
 				.instrument(span),
 			);
 		}
-		drop(batch);
 		set.await;
-		Ok(())
 	}
 }
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use fleet_base::{
+	deploy::{
+		deploy_task, load_receipt, DEFAULT_ROLLBACK_CONFIRM_RETRY_INTERVAL,
+		DEFAULT_ROLLBACK_CONFIRM_TIMEOUT,
+	},
+	host::Config,
+	opts::FleetOpts,
+};
+use tracing::{info, warn};
+
+/// Re-drives hosts whose last `fleet deploy`/`fleet rollback` got interrupted before activation,
+/// e.g. by a killed CLI or a dropped connection mid-deploy. Reads each host's deploy receipt
+/// (see [`fleet_base::deploy::DeployReceipt`]) and, for any that never reached
+/// [`fleet_base::deploy::ReceiptStep::Activated`], reruns the activation against the closure
+/// that was already uploaded, rather than re-uploading and re-building from scratch.
+#[derive(Parser)]
+pub struct Resume {
+	/// Only resume this host instead of scanning every host for an unfinished deploy
+	host: Option<String>,
+}
+
+impl Resume {
+	pub async fn run(self, config: &Config, opts: &FleetOpts) -> Result<()> {
+		let hosts = match &self.host {
+			Some(name) => vec![config.host(name).await?],
+			None => opts.filter_skipped(config.list_hosts().await?).await?,
+		};
+
+		let mut resumed = 0;
+		for host in hosts {
+			let Some(receipt) = load_receipt(config, &host.name) else {
+				continue;
+			};
+			if receipt.is_complete() {
+				continue;
+			}
+			info!(
+				"resuming interrupted {:?} deploy on {}, started at {}",
+				receipt.action, host.name, receipt.started_at
+			);
+
+			let rollback_confirm_timeout = opts
+				.action_attr::<u64>(&host, "rollback_confirm_timeout")
+				.await?
+				.map(Duration::from_secs)
+				.unwrap_or(DEFAULT_ROLLBACK_CONFIRM_TIMEOUT);
+			let rollback_confirm_retry_interval = opts
+				.action_attr::<u64>(&host, "rollback_confirm_retry_interval")
+				.await?
+				.map(Duration::from_secs)
+				.unwrap_or(DEFAULT_ROLLBACK_CONFIRM_RETRY_INTERVAL);
+			let specialisation = opts.action_attr(&host, "specialisation").await?;
+
+			if let Err(e) = deploy_task(
+				receipt.action,
+				&host,
+				receipt.built.clone(),
+				specialisation,
+				receipt.disable_rollback,
+				rollback_confirm_timeout,
+				rollback_confirm_retry_interval,
+				config,
+				receipt.previous_generation.clone(),
+			)
+			.await
+			{
+				warn!("failed to resume deploy on {}: {e}", host.name);
+			} else {
+				resumed += 1;
+			}
+		}
+
+		if resumed == 0 {
+			info!("no interrupted deploys found");
+		} else {
+			info!("resumed {resumed} interrupted deploy(s)");
+		}
+		Ok(())
+	}
+}
@@ -1,13 +1,16 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, time::Duration};
 
-use anyhow::{Result, bail};
+use anyhow::{Context as _, Result, bail};
 use clap::Parser;
 use fleet_base::{
-	deploy::{DeployAction, deploy_task, upload_task},
+	deploy::{
+		DEFAULT_ROLLBACK_CONFIRM_RETRY_INTERVAL, DEFAULT_ROLLBACK_CONFIRM_TIMEOUT, DeployAction,
+		deploy_task, load_receipt, upload_task,
+	},
 	host::{Config, ConfigHost, Generation, GenerationStorage},
 	opts::FleetOpts,
 };
-use tabled::Table;
+use tabled::{Table, Tabled};
 use tracing::{info, warn};
 
 #[derive(Parser)]
@@ -28,18 +31,103 @@ struct DeployOptions {
 	/// Specialization to use
 	#[clap(long)]
 	specialization: Option<String>,
+	/// Skip printing the closure diff against the current generation before Switch/Boot
+	#[clap(long)]
+	yes: bool,
 }
 
 #[derive(Parser, Clone)]
 enum RollbackAction {
 	/// List available rollback targets
 	ListTargets,
+	/// Show the package-level closure diff between the host's current generation and a
+	/// rollback target, without activating anything.
+	Diff {
+		/// Rollback target to diff against
+		id: String,
+	},
 	/// Upload and execute the activation script, old version will be used after reboot.
 	Test(#[clap(flatten)] DeployOptions),
 	/// Upload, set current profile, and execute activation script.
 	Switch(#[clap(flatten)] DeployOptions),
 	/// Upload and set as current system profile, but do not execute activation script.
 	Boot(#[clap(flatten)] DeployOptions),
+	/// Switch back to the generation recorded in the host's last deploy receipt, i.e. what
+	/// `rollback-watchdog.service` would have reverted to. Useful to revert a deploy by hand
+	/// without waiting out the watchdog timer, or after it was disarmed with `--disable-rollback`.
+	Auto {
+		/// Skip printing the closure diff against the current generation before switching
+		#[clap(long)]
+		yes: bool,
+	},
+}
+
+/// A single package's change between two closures, as shown by [`print_closure_diff`]; `old`/
+/// `new` are empty for additions/removals respectively.
+#[derive(Tabled)]
+struct ClosureDiffRow {
+	package: String,
+	old: String,
+	new: String,
+}
+
+/// Parses `nix store diff-closures` output: each line looks like `name: old -> new[, +X KiB]`,
+/// with `∅` standing in for "not present on this side" when a package was added or removed.
+fn parse_diff_closures(output: &str) -> Vec<ClosureDiffRow> {
+	fn normalize(side: &str) -> String {
+		let side = side.trim();
+		if side == "∅" { String::new() } else { side.to_owned() }
+	}
+	output
+		.lines()
+		.filter_map(|line| {
+			let (package, rest) = line.split_once(':')?;
+			let versions = rest.split(',').next().unwrap_or(rest);
+			let (old, new) = versions.split_once("->")?;
+			Some(ClosureDiffRow {
+				package: package.trim().to_owned(),
+				old: normalize(old),
+				new: normalize(new),
+			})
+		})
+		.collect()
+}
+
+/// Uploads `target` to wherever `current` already lives, so both closures are visible to a
+/// single `nix store diff-closures` invocation, then prints the package-level delta as a table.
+async fn print_closure_diff(
+	config: &Config,
+	host: &ConfigHost,
+	current: &Generation,
+	target: &Generation,
+) -> Result<()> {
+	let remote_current = upload_task(config, host, current.location, current.store_path.clone()).await?;
+	let remote_target = upload_task(config, host, target.location, target.store_path.clone()).await?;
+
+	let mut cmd = host.cmd("nix").await?;
+	cmd.arg("store")
+		.arg("diff-closures")
+		.arg(&remote_current)
+		.arg(&remote_target);
+	let output = cmd
+		.run_string()
+		.await
+		.context("running nix store diff-closures")?;
+
+	let rows = parse_diff_closures(&output);
+	if rows.is_empty() {
+		info!(
+			"no package differences between the current generation and {}",
+			target.rollback_id()
+		);
+	} else {
+		info!(
+			"closure diff, current generation -> {}:\n{}",
+			target.rollback_id(),
+			Table::new(&rows)
+		);
+	}
+	Ok(())
 }
 
 pub async fn list_all_generations(host: &ConfigHost, config: &Config) -> Vec<Generation> {
@@ -74,7 +162,7 @@ pub async fn list_all_generations(host: &ConfigHost, config: &Config) -> Vec<Gen
 }
 
 impl RollbackSingle {
-	pub(crate) async fn run(&self, config: &Config, _opts: &FleetOpts) -> Result<()> {
+	pub(crate) async fn run(&self, config: &Config, opts: &FleetOpts) -> Result<()> {
 		let host = config.host(&self.machine).await?;
 		match &self.action {
 			RollbackAction::ListTargets => {
@@ -85,11 +173,74 @@ impl RollbackSingle {
 				info!("Generation list:\n{}", Table::new(&generations));
 				Ok(())
 			}
+			RollbackAction::Diff { id } => {
+				let generations = list_all_generations(&host, config).await;
+				let Some(target) = generations.iter().find(|g| &g.rollback_id() == id) else {
+					bail!(
+						"generation by this name is not found, existing generations:\n{}",
+						Table::new(&generations)
+					);
+				};
+				let Some(current) = generations.iter().find(|g| g.current) else {
+					bail!("failed to find the host's current generation");
+				};
+				print_closure_diff(config, &host, current, target).await
+			}
+			RollbackAction::Auto { yes } => {
+				let Some(receipt) = load_receipt(config, &self.machine) else {
+					bail!("no deploy receipt recorded for {:?}, nothing to automatically roll back to", self.machine);
+				};
+				let Some(target_id) = &receipt.previous_generation else {
+					bail!("last deploy receipt for {:?} has no recorded previous generation", self.machine);
+				};
+				let generations = list_all_generations(&host, config).await;
+				let Some(target) = generations.iter().find(|g| &g.rollback_id() == target_id) else {
+					bail!(
+						"recorded rollback target {target_id:?} is no longer available, existing generations:\n{}",
+						Table::new(&generations)
+					);
+				};
+				if !yes {
+					if let Some(current) = generations.iter().find(|g| g.current) {
+						if let Err(e) = print_closure_diff(config, &host, current, target).await {
+							warn!("failed to compute closure diff, proceeding without it: {e}");
+						}
+					}
+				}
+				let remote_path =
+					upload_task(config, &host, target.location, target.store_path.clone()).await?;
+
+				let rollback_confirm_timeout = opts
+					.action_attr::<u64>(&host, "rollback_confirm_timeout")
+					.await?
+					.map(Duration::from_secs)
+					.unwrap_or(DEFAULT_ROLLBACK_CONFIRM_TIMEOUT);
+				let rollback_confirm_retry_interval = opts
+					.action_attr::<u64>(&host, "rollback_confirm_retry_interval")
+					.await?
+					.map(Duration::from_secs)
+					.unwrap_or(DEFAULT_ROLLBACK_CONFIRM_RETRY_INTERVAL);
+
+				deploy_task(
+					DeployAction::Switch,
+					&host,
+					remote_path,
+					None,
+					false,
+					rollback_confirm_timeout,
+					rollback_confirm_retry_interval,
+					config,
+					None,
+				)
+				.await?;
+				Ok(())
+			}
 			RollbackAction::Boot(o) | RollbackAction::Test(o) | RollbackAction::Switch(o) => {
 				let DeployOptions {
 					id,
 					enable_rollback,
 					specialization,
+					yes,
 				} = o;
 				let action: DeployAction = match self.action {
 					RollbackAction::Test { .. } => DeployAction::Test,
@@ -104,6 +255,13 @@ impl RollbackSingle {
 						Table::new(&generations)
 					);
 				};
+				if !yes && matches!(action, DeployAction::Switch | DeployAction::Boot) {
+					if let Some(current) = generations.iter().find(|g| g.current) {
+						if let Err(e) = print_closure_diff(config, &host, current, generation).await {
+							warn!("failed to compute closure diff, proceeding without it: {e}");
+						}
+					}
+				}
 				let remote_path = upload_task(
 					config,
 					&host,
@@ -112,12 +270,27 @@ impl RollbackSingle {
 				)
 				.await?;
 
+				let rollback_confirm_timeout = opts
+					.action_attr::<u64>(&host, "rollback_confirm_timeout")
+					.await?
+					.map(Duration::from_secs)
+					.unwrap_or(DEFAULT_ROLLBACK_CONFIRM_TIMEOUT);
+				let rollback_confirm_retry_interval = opts
+					.action_attr::<u64>(&host, "rollback_confirm_retry_interval")
+					.await?
+					.map(Duration::from_secs)
+					.unwrap_or(DEFAULT_ROLLBACK_CONFIRM_RETRY_INTERVAL);
+
 				deploy_task(
 					action,
 					&host,
 					remote_path,
 					specialization.clone(),
 					!*enable_rollback,
+					rollback_confirm_timeout,
+					rollback_confirm_retry_interval,
+					config,
+					None,
 				)
 				.await?;
 				Ok(())
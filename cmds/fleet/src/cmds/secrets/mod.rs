@@ -1,26 +1,34 @@
 use std::{
 	collections::{BTreeMap, BTreeSet, HashSet},
-	io::{self, Read, Write, stdin, stdout},
+	ffi::OsString,
+	io::{self, IsTerminal, Read, Write, stdin, stdout},
 	path::PathBuf,
 };
 
 use anyhow::{Context, Result, anyhow, bail, ensure};
 use chrono::{DateTime, Utc};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use crossterm::terminal;
+use dialoguer::{FuzzySelect, Input, theme::ColorfulTheme};
 use fleet_base::{
+	age_plugin,
 	fleetdata::{
 		FleetHostSecret, FleetSecretData, FleetSecretPart, FleetSharedSecret, encrypt_secret_data,
 	},
 	host::Config,
+	mnemonic,
 	opts::FleetOpts,
 	secret::{Expectations, RegenerationReason, SharedSecretDefinition, secret_needs_regeneration},
+	shamir,
+	template::{SecretTemplate, parse as parse_template, render as render_template},
 };
 use fleet_shared::SecretData;
+use itertools::Itertools;
 use nix_eval::{NixType, Value, nix_go, nix_go_json};
 use owo_colors::OwoColorize;
 use serde::Deserialize;
 use tabled::{Table, Tabled};
-use tokio::{fs::read, task::spawn_blocking};
+use tokio::{fs::read, process::Command, task::spawn_blocking};
 use tracing::{Instrument, error, info, info_span, warn};
 
 #[derive(Parser)]
@@ -59,6 +67,12 @@ pub enum Secret {
 		/// How to name private secret part
 		#[clap(short = 's', long, default_value = "secret")]
 		part: String,
+
+		/// Require this many owners (out of the total passed to --machines) together to
+		/// reconstruct the secret, via Shamir's secret sharing. Omit for the default, where any
+		/// single owner can decrypt it alone.
+		#[clap(long)]
+		threshold: Option<u32>,
 	},
 	/// Add secret, data should be provided in stdin
 	Add {
@@ -122,6 +136,12 @@ pub enum Secret {
 		/// Which host should we use to decrypt
 		#[clap(long)]
 		prefer_identities: Vec<String>,
+
+		/// If machines are being removed, force full regeneration of the secret value instead of
+		/// just leaving the removed owners' stanzas in place, so their copy becomes worthless.
+		/// Overrides the secret's `regenerateOnOwnerRemoved` definition for this one update.
+		#[clap(long)]
+		rotate_on_remove: bool,
 	},
 	Regenerate {
 		/// Which host should we use to decrypt, in case if reencryption is required, without
@@ -132,21 +152,77 @@ pub enum Secret {
 		#[clap(long)]
 		skip_hosts: bool,
 	},
-	List {},
+	List {
+		#[clap(long, value_enum)]
+		format: Option<ListFormat>,
+	},
+	/// Edit a secret part in `$EDITOR`. Any of `name`/`machine`/`part` may be omitted when
+	/// attached to a terminal, in which case they're picked interactively via a fuzzy selector.
 	Edit {
-		name: String,
+		/// Secret name; omit (interactively) to pick from the machine's configured secrets
+		name: Option<String>,
+		/// Secret owner; omit (interactively) to pick from configured hosts
 		#[clap(short = 'm', long)]
-		machine: String,
+		machine: Option<String>,
 
 		#[clap(long)]
 		add: bool,
 
-		/// Which private secret part to read
-		#[clap(short = 'p', long, default_value = "secret")]
-		part: String,
+		/// Which private secret part to read; omit (interactively) to pick from the secret's
+		/// parts, with the option to add a new one. Defaults to "secret" when not interactive.
+		#[clap(short = 'p', long)]
+		part: Option<String>,
+	},
+	/// Back up a host's age identity as a mnemonic phrase, printed to stdout. This is fleet's own
+	/// word list and checksum scheme, not BIP39 — it isn't readable by BIP39 wallet tooling.
+	BackupKey {
+		machine: String,
+	},
+	/// Restore a host's age identity from a mnemonic phrase produced by `backup-key`, provided on
+	/// stdin
+	RecoverKey {
+		machine: String,
+		/// If the phrase as typed doesn't decode, try every single-word correction and accept
+		/// it if exactly one restores a valid checksum
+		#[clap(long)]
+		fuzzy: bool,
+	},
+	/// Survey every host secret and shared secret's expiration/regeneration state
+	Audit {
+		#[clap(long, value_enum)]
+		format: Option<AuditFormat>,
+		/// Only list secrets that have entered their proactive rotation window (still valid, but
+		/// due to be rotated soon), without affecting any other secret's listing and without
+		/// regenerating anything - run `secrets regenerate` separately to act on these.
+		#[clap(long)]
+		expiring_soon: bool,
 	},
 }
 
+#[derive(ValueEnum, Clone, Copy)]
+enum AuditFormat {
+	/// Human-readable table (default)
+	Table,
+	/// Prometheus text exposition format, for scraping
+	Prometheus,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum ListFormat {
+	/// Human-readable table (default)
+	Table,
+	/// One JSON object per secret, for scripting/CI
+	Json,
+}
+
+#[derive(serde::Serialize)]
+struct SecretListEntry {
+	name: String,
+	owners: BTreeSet<String>,
+	expected: BTreeSet<String>,
+	in_sync: bool,
+}
+
 #[allow(clippy::too_many_arguments)]
 #[tracing::instrument(skip(config, secret, definition, prefer_identities))]
 async fn maybe_regenerate_shared_secret(
@@ -156,11 +232,27 @@ async fn maybe_regenerate_shared_secret(
 	definition: SharedSecretDefinition,
 	prefer_identities: &[String],
 	expectations: &Expectations,
+	force_rotate_on_remove: bool,
 ) -> Result<FleetSharedSecret> {
 	let reason = secret_needs_regeneration(&secret.secret, &secret.owners, expectations);
+
+	if let Some(threshold) = secret.secret.threshold.filter(|t| *t > 1) {
+		if matches!(
+			reason,
+			Some(RegenerationReason::OwnersAdded(_) | RegenerationReason::OwnersRemoved(_))
+		) {
+			// Re-splitting already hands removed owners' old shares a fresh, unrelated set of
+			// points, so below-threshold old shares are worthless without any extra rotation
+			// step: the security guarantee this function's `rotate_on_remove` handling exists
+			// for is already met here.
+			info!("threshold secret's owner set changed, re-splitting shares");
+			return resplit_shared_secret(config, secret, expectations, threshold).await;
+		}
+	}
+
 	let value = definition.definition_value();
 
-	let (should_reencrypt, reason) = match reason {
+	let (should_reencrypt, reason, revoked_owners) = match reason {
 		Some(RegenerationReason::OwnersAdded(_)) => {
 			// Secret always needs to be reencrypted for new owners to be able to read it
 			(
@@ -170,23 +262,36 @@ async fn maybe_regenerate_shared_secret(
 				} else {
 					None
 				},
+				None,
 			)
 		}
-		Some(RegenerationReason::OwnersRemoved(_)) => {
-			// No need to reencrypt, we can just leave stanzas in place.
-			if nix_go_json!(value.regenerateOnOwnerRemoved) {
-				(true, reason)
+		Some(RegenerationReason::OwnersRemoved(ref removed)) => {
+			let rotate = force_rotate_on_remove || expectations.rotate_on_remove;
+			if rotate {
+				ensure!(
+					definition.is_managed()?,
+					"can't rotate {secret_name:?}: it has no generator defined, so there's nothing \
+					 to regenerate automatically; re-add it manually (`secrets add-shared --re-add`) \
+					 to rotate it"
+				);
+				(true, reason, Some(removed.clone()))
 			} else {
-				(false, None)
+				// No need to reencrypt, we can just leave stanzas in place.
+				(false, None, None)
 			}
 		}
-		Some(_) => (true, reason),
-		None => (false, None),
+		Some(_) => (true, reason, None),
+		None => (false, None, None),
 	};
 
 	if let Some(reason) = reason {
 		info!("secret needs to be regenerated: {reason}");
-		let generated = generate_shared(config, secret_name, definition, expectations).await?;
+		let mut generated = generate_shared(config, secret_name, definition, expectations).await?;
+		if let Some(revoked_owners) = revoked_owners {
+			info!("revoking access for removed owners: {revoked_owners:?}");
+			generated.secret.revoked_at = Some(Utc::now());
+			generated.secret.previous_owners = revoked_owners;
+		}
 		Ok(generated)
 	} else if should_reencrypt {
 		info!("secret needs to be reencrypted");
@@ -206,13 +311,26 @@ async fn maybe_regenerate_shared_secret(
 			if !part.raw.encrypted {
 				continue;
 			}
-			let host = config.host(identity_holder).await?;
-			let encrypted = host
-				.reencrypt(
+			let key = config.key(identity_holder).await?;
+			let encrypted = if let Some(stanza) = age_plugin::parse_plugin_stanza(&key) {
+				// `ConfigHost::reencrypt` only knows the host's regular identity-file decrypt,
+				// which can't speak to a plugin-backed identity - decrypt via the plugin
+				// ourselves and re-encrypt to the new owner set instead.
+				let host = config.host(identity_holder).await?;
+				let plaintext = age_plugin::run_plugin_identity(&host, &stanza, &part.raw.data).await?;
+				let recipients = config
+					.recipients(expectations.owners.iter().cloned().collect())
+					.await?;
+				encrypt_secret_data(recipients.iter(), plaintext)
+					.ok_or_else(|| anyhow!("no recipients provided"))?
+			} else {
+				let host = config.host(identity_holder).await?;
+				host.reencrypt(
 					part.raw.clone(),
 					expectations.owners.iter().cloned().collect(),
 				)
-				.await?;
+				.await?
+			};
 			part.raw = encrypted;
 		}
 		secret.owners = expectations.owners.clone();
@@ -230,13 +348,73 @@ enum GeneratorKind {
 }
 
 async fn generate_pure(
-	_config: &Config,
+	config: &Config,
 	_display_name: &str,
-	_secret: Value,
+	secret: Value,
 	_default_generator: Value,
-	_expectations: &Expectations,
+	expectations: &Expectations,
 ) -> Result<FleetSecretData> {
-	bail!("pure generators are broken for now")
+	let generator = nix_go!(secret.generator);
+
+	let host = config.local_host();
+	let nixpkgs = &config.nixpkgs;
+	let default_pkgs = &config.default_pkgs;
+
+	let mut recipients = Vec::new();
+	for owner in &expectations.owners {
+		let key = config.key(owner).await?;
+		recipients.push(key);
+	}
+	let mk_secret_generators = nix_go!(default_pkgs.mkSecretGenerators);
+	let generators = nix_go!(mk_secret_generators(Obj { recipients }));
+	let pkgs_and_generators = default_pkgs.clone().attrs_update(generators)?;
+
+	let call_package = nix_go!(nixpkgs.lib.callPackageWith(pkgs_and_generators));
+	let generator = nix_go!(call_package(generator)(Obj {}));
+
+	let generator = spawn_blocking(move || generator.build("out"))
+		.await
+		.expect("nix build shouldn't fail")?;
+	// Unlike `generate_impure`, a pure generator's build output already *is* the finished
+	// marker/parts/created_at/expires_at layout - there's no script to invoke afterwards, no
+	// `impureOn` host to pick, and no `mktemp_dir`/`FLEET_PROJECT` to wire up. `remote_derivation`
+	// here only resolves the built store path for reading.
+	let out = host.remote_derivation(&generator).await?;
+
+	let marker = host.read_file_text(format!("{out}/marker")).await?;
+	ensure!(marker == "SUCCESS", "generation not succeeded");
+
+	let mut parts = BTreeMap::new();
+	for part in host.read_dir(&out).await? {
+		if part == "created_at" || part == "expires_at" || part == "marker" {
+			continue;
+		}
+		let contents: SecretData = host
+			.read_file_text(format!("{out}/{part}"))
+			.await?
+			.parse()
+			.map_err(|e| anyhow!("failed to decode secret {out:?} part {part:?}: {e}"))?;
+		parts.insert(part.to_owned(), FleetSecretPart { raw: contents });
+	}
+
+	let created_at = host.read_file_value(format!("{out}/created_at")).await?;
+	let expires_at = host.read_file_value(format!("{out}/expires_at")).await.ok();
+
+	let new_data = FleetSecretData {
+		created_at,
+		expires_at,
+		parts,
+		generation_data: expectations.generation_data.clone(),
+		threshold: expectations.threshold,
+		revoked_at: None,
+		previous_owners: BTreeSet::new(),
+	};
+
+	if let Some(reason) = secret_needs_regeneration(&new_data, &expectations.owners, expectations) {
+		bail!("newly generated secret needs to be regenerated: {reason}")
+	}
+
+	Ok(new_data)
 }
 async fn generate_impure(
 	config: &Config,
@@ -318,6 +496,12 @@ async fn generate_impure(
 		expires_at,
 		parts,
 		generation_data: expectations.generation_data.clone(),
+		// Generator-managed secrets don't produce Shamir shares themselves yet (see the
+		// `ensure!` in `generate_shared`), so there's nothing to record here beyond "unsplit".
+		threshold: expectations.threshold,
+		// Set by `maybe_regenerate_shared_secret` after a rotating regeneration, not here.
+		revoked_at: None,
+		previous_owners: BTreeSet::new(),
 	};
 
 	if let Some(reason) = secret_needs_regeneration(&new_data, &expectations.owners, expectations) {
@@ -399,6 +583,12 @@ async fn generate_shared(
 	expectations: &Expectations,
 ) -> Result<FleetSharedSecret> {
 	// let owners: Vec<String> = nix_go_json!(secret.expectedOwners);
+	ensure!(
+		expectations.threshold.unwrap_or(1) <= 1,
+		"threshold shared secrets are only supported via `secrets add-shared --threshold`, \
+		 generator-managed secret {display_name:?} requested threshold {:?}",
+		expectations.threshold
+	);
 	Ok(FleetSharedSecret {
 		managed: Some(true),
 		secret: generate(
@@ -442,11 +632,34 @@ async fn parse_secret() -> Result<Option<Vec<u8>>> {
 	}
 }
 
+/// Key under which a single owner's Shamir share of `part_name` is stored in
+/// [`FleetSecretData::parts`], for threshold shared secrets. Non-threshold parts are stored
+/// under the bare part name instead; see [`SharedSecretDefinition`]/[`shamir`].
+fn share_key(part_name: &str, owner: &str) -> String {
+	format!("{part_name}@{owner}")
+}
+
+/// Decrypts `data`, which was encrypted to `owner`, transparently routing through `owner`'s
+/// [`age_plugin`] binary when their registered key is plugin-backed (`age1<name>1...`/
+/// `AGE-PLUGIN-<NAME>-1...`, see [`age_plugin::parse_plugin_stanza`]) rather than the host's
+/// regular identity-file decrypt - the plugin case is every `host.decrypt` call site in this
+/// module that reads an owner-held secret (`Secret::Read`/`ReadShared`, and threshold
+/// reconstruction in [`gather_and_reconstruct`]).
+async fn decrypt_owned(config: &Config, owner: &str, data: SecretData) -> Result<Vec<u8>> {
+	let host = config.host(owner).await?;
+	let key = config.key(owner).await?;
+	if let Some(stanza) = age_plugin::parse_plugin_stanza(&key) {
+		return age_plugin::run_plugin_identity(&host, &stanza, &data.data).await;
+	}
+	host.decrypt(data).await
+}
+
 fn parse_machines(
 	initial: BTreeSet<String>,
 	machines: Option<Vec<String>>,
 	mut add_machines: Vec<String>,
 	mut remove_machines: Vec<String>,
+	min_owners: Option<u32>,
 ) -> Result<BTreeSet<String>> {
 	if machines.is_none() && add_machines.is_empty() && remove_machines.is_empty() {
 		bail!("no operation");
@@ -482,14 +695,303 @@ fn parse_machines(
 		}
 	}
 	if !remove_machines.is_empty() {
-		// TODO: maybe force secret regeneration?
-		// Not that useful without revokation.
 		warn!(
-			"secret will not be regenerated for removed machines, and until host rebuild, they will still possess the ability to decode secret"
+			"removed machines will still possess the ability to decode the secret's current value \
+			 unless it's regenerated; pass `--rotate-on-remove` (or set the secret's \
+			 `regenerateOnOwnerRemoved` policy) to force that"
+		);
+	}
+	if let Some(min_owners) = min_owners {
+		ensure!(
+			target_machines.len() >= min_owners as usize,
+			"this would leave {} owner(s), below the secret's threshold of {min_owners}",
+			target_machines.len()
 		);
 	}
 	Ok(target_machines)
 }
+
+/// Each owner's share point is its 1-based position in `secret.owners`'s sorted (`BTreeSet`)
+/// order; this is stable for as long as the owner set itself doesn't change, and a changed
+/// owner set always forces a re-split (see [`resplit_shared_secret`]) that reassigns points.
+fn share_point(owners: &BTreeSet<String>, owner: &str) -> Result<u8> {
+	owners
+		.iter()
+		.position(|o| o == owner)
+		.map(|i| i as u8 + 1)
+		.ok_or_else(|| anyhow!("{owner:?} is not an owner of this secret"))
+}
+
+/// Decrypts `threshold` of `secret`'s shares of `part_name` (preferring `prefer_identities`,
+/// then falling back to the rest of `secret.owners` in order) and reconstructs the original
+/// bytes via [`shamir::reconstruct`].
+async fn gather_and_reconstruct(
+	config: &Config,
+	secret: &FleetSharedSecret,
+	part_name: &str,
+	threshold: u32,
+	prefer_identities: &[String],
+) -> Result<Vec<u8>> {
+	let ordered_owners = prefer_identities
+		.iter()
+		.filter(|i| secret.owners.contains(*i))
+		.chain(secret.owners.iter().filter(|o| !prefer_identities.contains(o)));
+
+	let mut shares = Vec::new();
+	for owner in ordered_owners {
+		if shares.len() >= threshold as usize {
+			break;
+		}
+		let Some(share) = secret.secret.parts.get(&share_key(part_name, owner)) else {
+			continue;
+		};
+		let x = share_point(&secret.owners, owner)?;
+		let plaintext = if share.raw.encrypted {
+			decrypt_owned(config, owner, share.raw.clone()).await?
+		} else {
+			share.raw.data.clone()
+		};
+		shares.push((x, plaintext));
+	}
+	ensure!(
+		shares.len() >= threshold as usize,
+		"need {threshold} shares of {part_name:?} to reconstruct it, only {} available",
+		shares.len()
+	);
+	shamir::reconstruct(&shares)
+}
+
+/// Re-splits every threshold-shared part of `secret` for its new owner set: reconstructs each
+/// part's plaintext from `threshold` of the old shares, then splits it again into fresh shares
+/// assigned to `expectations.owners`'s points, encrypted to their respective recipients. Needed
+/// whenever the owner set of a threshold secret changes, since share points are derived from
+/// owner position and so shift whenever an owner is added or removed.
+async fn resplit_shared_secret(
+	config: &Config,
+	mut secret: FleetSharedSecret,
+	expectations: &Expectations,
+	threshold: u32,
+) -> Result<FleetSharedSecret> {
+	let new_owners = &expectations.owners;
+	ensure!(
+		threshold as usize <= new_owners.len(),
+		"threshold ({threshold}) can't exceed the new owner count ({})",
+		new_owners.len()
+	);
+
+	let part_names: BTreeSet<String> = secret
+		.secret
+		.parts
+		.keys()
+		.filter_map(|key| key.split_once('@').map(|(base, _)| base.to_owned()))
+		.collect();
+
+	let mut new_parts = BTreeMap::new();
+	for part_name in &part_names {
+		let plaintext = gather_and_reconstruct(config, &secret, part_name, threshold, &[]).await?;
+		let owners: Vec<String> = new_owners.iter().cloned().collect();
+		let shares = shamir::split(&plaintext, threshold, owners.len() as u32)?;
+		for (owner, (_, share)) in owners.iter().zip(shares) {
+			let recipient = config.recipient(owner).await?;
+			let encrypted = encrypt_secret_data([&recipient], share).expect("recipient provided");
+			new_parts.insert(share_key(part_name, owner), FleetSecretPart { raw: encrypted });
+		}
+	}
+	// Parts stored without an owner suffix (e.g. a public part) aren't shares; keep them as-is.
+	for (key, part) in std::mem::take(&mut secret.secret.parts) {
+		if !key.contains('@') {
+			new_parts.insert(key, part);
+		}
+	}
+
+	secret.secret.parts = new_parts;
+	secret.secret.threshold = Some(threshold);
+	secret.owners = new_owners.clone();
+	Ok(secret)
+}
+/// One host secret or shared secret's audited state, as surveyed by `secrets audit`.
+struct AuditEntry {
+	name: String,
+	/// `None` for a shared secret, `Some(host name)` for a host secret.
+	machine: Option<String>,
+	owners: BTreeSet<String>,
+	created_at: Option<DateTime<Utc>>,
+	expires_at: Option<DateTime<Utc>>,
+	/// Human-readable reason the secret needs regeneration, if any; `"not yet generated"` if
+	/// it's defined but has never been created.
+	regeneration_reason: Option<String>,
+	/// Whether `regeneration_reason` is specifically [`RegenerationReason::ExpiringSoon`], i.e.
+	/// the secret is still valid but has entered its proactive rotation window - distinct from
+	/// already being expired or any other divergence. Used by `secrets audit --expiring-soon` to
+	/// filter down to just those, without triggering any actual regeneration.
+	expiring_soon: bool,
+}
+
+#[derive(Tabled)]
+struct AuditRow {
+	#[tabled(rename = "Name")]
+	name: String,
+	#[tabled(rename = "Machine")]
+	machine: String,
+	#[tabled(rename = "Owners")]
+	owners: String,
+	#[tabled(rename = "Created At")]
+	created_at: String,
+	#[tabled(rename = "Expires At")]
+	expires_at: String,
+	#[tabled(rename = "TTL")]
+	ttl: String,
+	#[tabled(rename = "Regeneration")]
+	regeneration: String,
+}
+impl From<&AuditEntry> for AuditRow {
+	fn from(entry: &AuditEntry) -> Self {
+		Self {
+			name: entry.name.clone(),
+			machine: entry.machine.clone().unwrap_or_else(|| "(shared)".to_owned()),
+			owners: entry.owners.iter().cloned().collect::<Vec<_>>().join(", "),
+			created_at: entry
+				.created_at
+				.map(|t| t.to_rfc3339())
+				.unwrap_or_else(|| "-".to_owned()),
+			expires_at: entry
+				.expires_at
+				.map(|t| t.to_rfc3339())
+				.unwrap_or_else(|| "-".to_owned()),
+			ttl: format_ttl(entry.expires_at),
+			regeneration: entry.regeneration_reason.clone().unwrap_or_else(|| "ok".to_owned()),
+		}
+	}
+}
+
+fn format_ttl(expires_at: Option<DateTime<Utc>>) -> String {
+	let Some(expires_at) = expires_at else {
+		return "-".to_owned();
+	};
+	let seconds = (expires_at - Utc::now()).num_seconds();
+	if seconds < 0 {
+		format!("expired {}s ago", -seconds)
+	} else {
+		format!("{seconds}s")
+	}
+}
+
+/// Renders `entries` as Prometheus text exposition format: one gauge for seconds until
+/// expiration (only for secrets that have one), one for whether regeneration is needed.
+fn render_audit_prometheus(entries: &[AuditEntry]) -> String {
+	use std::fmt::Write;
+
+	let mut out = String::new();
+	let _ = writeln!(
+		out,
+		"# HELP fleet_secret_expires_in_seconds Seconds until the secret expires (negative if already expired)."
+	);
+	let _ = writeln!(out, "# TYPE fleet_secret_expires_in_seconds gauge");
+	for entry in entries {
+		if let Some(expires_at) = entry.expires_at {
+			let seconds = (expires_at - Utc::now()).num_seconds();
+			let _ = writeln!(
+				out,
+				"fleet_secret_expires_in_seconds{{name={:?},machine={:?}}} {seconds}",
+				entry.name,
+				entry.machine.as_deref().unwrap_or("")
+			);
+		}
+	}
+	let _ = writeln!(
+		out,
+		"# HELP fleet_secret_needs_regeneration Whether the secret's stored state diverges from its current expectations."
+	);
+	let _ = writeln!(out, "# TYPE fleet_secret_needs_regeneration gauge");
+	for entry in entries {
+		let _ = writeln!(
+			out,
+			"fleet_secret_needs_regeneration{{name={:?},machine={:?}}} {}",
+			entry.name,
+			entry.machine.as_deref().unwrap_or(""),
+			entry.regeneration_reason.is_some() as u8
+		);
+	}
+	out
+}
+
+async fn collect_audit_entries(config: &Config, opts: &FleetOpts) -> Result<Vec<AuditEntry>> {
+	let mut entries = Vec::new();
+
+	for host in config.list_hosts().await? {
+		if opts.should_skip(&host).await? {
+			continue;
+		}
+		for secret_name in host.list_defined_secrets()? {
+			let definition = host.secret_definition(&secret_name)?;
+			if definition.is_shared()? {
+				continue;
+			}
+			let expectations = definition
+				.expectations()
+				.with_context(|| format!("expectations for {secret_name:?} of {:?}", host.name))?;
+
+			if !config.has_secret(&host.name, &secret_name) {
+				entries.push(AuditEntry {
+					name: secret_name,
+					machine: Some(host.name.clone()),
+					owners: expectations.owners,
+					created_at: None,
+					expires_at: None,
+					regeneration_reason: Some("not yet generated".to_owned()),
+					expiring_soon: false,
+				});
+				continue;
+			}
+			let data = config.host_secret(&host.name, &secret_name)?;
+			let reason = secret_needs_regeneration(&data.secret, &expectations.owners, &expectations);
+			let expiring_soon = matches!(reason, Some(RegenerationReason::ExpiringSoon { .. }));
+			entries.push(AuditEntry {
+				name: secret_name,
+				machine: Some(host.name.clone()),
+				owners: expectations.owners,
+				created_at: Some(data.secret.created_at),
+				expires_at: data.secret.expires_at,
+				regeneration_reason: reason.map(|reason| reason.to_string()),
+				expiring_soon,
+			});
+		}
+	}
+
+	for name in config.list_configured_shared().await? {
+		let definition = config.shared_secret_definition(&name)?;
+		let expectations = definition
+			.expectations()
+			.with_context(|| format!("expectations for shared {name:?}"))?;
+
+		let Some(data) = config.shared_secret(&name)? else {
+			entries.push(AuditEntry {
+				name,
+				machine: None,
+				owners: expectations.owners,
+				created_at: None,
+				expires_at: None,
+				regeneration_reason: Some("not yet generated".to_owned()),
+				expiring_soon: false,
+			});
+			continue;
+		};
+		let reason = secret_needs_regeneration(&data.secret, &data.owners, &expectations);
+		let expiring_soon = matches!(reason, Some(RegenerationReason::ExpiringSoon { .. }));
+		entries.push(AuditEntry {
+			name,
+			machine: None,
+			owners: data.owners,
+			created_at: Some(data.secret.created_at),
+			expires_at: data.secret.expires_at,
+			regeneration_reason: reason.map(|reason| reason.to_string()),
+			expiring_soon,
+		});
+	}
+
+	Ok(entries)
+}
+
 impl Secret {
 	pub async fn run(self, config: &Config, opts: &FleetOpts) -> Result<()> {
 		match self {
@@ -514,6 +1016,7 @@ impl Secret {
 				expires_at,
 				re_add,
 				part: part_name,
+				threshold,
 			} => {
 				let mut machines: BTreeSet<String> = machines.into_iter().collect();
 				// TODO: Forbid updating secrets with set expectedOwners (= not user-managed).
@@ -548,9 +1051,20 @@ impl Secret {
 				io::stdin().read_to_end(&mut input)?;
 
 				if !input.is_empty() {
-					let encrypted = encrypt_secret_data(recipients.iter(), input)
-						.ok_or_else(|| anyhow!("no recipients provided"))?;
-					parts.insert(part_name, FleetSecretPart { raw: encrypted });
+					if let Some(threshold) = threshold {
+						let owners: Vec<String> = machines.iter().cloned().collect();
+						let shares = shamir::split(&input, threshold, owners.len() as u32)?;
+						for (owner, (_, share)) in owners.iter().zip(shares) {
+							let recipient = config.recipient(owner).await?;
+							let encrypted =
+								encrypt_secret_data([&recipient], share).expect("recipient provided");
+							parts.insert(share_key(&part_name, owner), FleetSecretPart { raw: encrypted });
+						}
+					} else {
+						let encrypted = encrypt_secret_data(recipients.iter(), input)
+							.ok_or_else(|| anyhow!("no recipients provided"))?;
+						parts.insert(part_name, FleetSecretPart { raw: encrypted });
+					}
 				}
 
 				if let Some(public) = parse_public(public, public_file).await? {
@@ -567,6 +1081,9 @@ impl Secret {
 							expires_at,
 							parts,
 							generation_data: serde_json::Value::Null,
+							threshold,
+							revoked_at: None,
+							previous_owners: BTreeSet::new(),
 						},
 					},
 				);
@@ -599,6 +1116,10 @@ impl Secret {
 							expires_at: None,
 							parts: BTreeMap::new(),
 							generation_data: serde_json::Value::Null,
+							// Host secrets have a single owner, no threshold to split across.
+							threshold: None,
+							revoked_at: None,
+							previous_owners: BTreeSet::new(),
 						},
 					}
 				};
@@ -649,8 +1170,7 @@ impl Secret {
 					bail!("no part {part_name} in secret {name}");
 				};
 				let data = if secret.raw.encrypted {
-					let host = config.host(&machine).await?;
-					host.decrypt(secret.raw.clone()).await?
+					decrypt_owned(config, &machine, secret.raw.clone()).await?
 				} else {
 					secret.raw.data.clone()
 				};
@@ -665,24 +1185,31 @@ impl Secret {
 				let Some(secret) = config.shared_secret(&name)? else {
 					bail!("secret doesn't exists");
 				};
-				let Some(part) = secret.secret.parts.get(&part_name) else {
-					bail!("no part {part_name} in secret {name}");
-				};
-				let data = if part.raw.encrypted {
-					let identity_holder = if !prefer_identities.is_empty() {
-						prefer_identities
-							.iter()
-							.find(|i| secret.owners.iter().any(|s| s == *i))
-					} else {
-						secret.owners.first()
-					};
-					let Some(identity_holder) = identity_holder else {
-						bail!("no available holder found");
-					};
-					let host = config.host(identity_holder).await?;
-					host.decrypt(part.raw.clone()).await?
-				} else {
-					part.raw.data.clone()
+				let data = match secret.secret.threshold {
+					Some(threshold) if threshold > 1 => {
+						gather_and_reconstruct(config, &secret, &part_name, threshold, &prefer_identities)
+							.await?
+					}
+					_ => {
+						let Some(part) = secret.secret.parts.get(&part_name) else {
+							bail!("no part {part_name} in secret {name}");
+						};
+						if part.raw.encrypted {
+							let identity_holder = if !prefer_identities.is_empty() {
+								prefer_identities
+									.iter()
+									.find(|i| secret.owners.iter().any(|s| s == *i))
+							} else {
+								secret.owners.first()
+							};
+							let Some(identity_holder) = identity_holder else {
+								bail!("no available holder found");
+							};
+							decrypt_owned(config, identity_holder, part.raw.clone()).await?
+						} else {
+							part.raw.data.clone()
+						}
+					}
 				};
 				stdout().write_all(&data)?;
 			}
@@ -692,6 +1219,7 @@ impl Secret {
 				add_machine,
 				remove_machine,
 				prefer_identities,
+				rotate_on_remove,
 			} => {
 				// TODO: Forbid updating secrets with set expectedOwners (= not user-managed).
 
@@ -708,6 +1236,7 @@ impl Secret {
 					machine,
 					add_machine,
 					remove_machine,
+					secret.secret.threshold,
 				)?;
 
 				if target_machines.is_empty() {
@@ -728,6 +1257,7 @@ impl Secret {
 					definition,
 					&prefer_identities,
 					&expectations,
+					rotate_on_remove,
 				)
 				.await?;
 				config.replace_shared(name, updated);
@@ -870,6 +1400,10 @@ impl Secret {
 							definition,
 							&prefer_identities,
 							&expectations,
+							// No CLI override here: `Regenerate` sweeps every shared secret at
+							// once, so whether to rotate on removal is governed purely by each
+							// secret's own `regenerateOnOwnerRemoved` policy (`expectations`).
+							false,
 						)
 						.await?,
 					);
@@ -879,39 +1413,65 @@ impl Secret {
 					config.remove_shared(removed_secret);
 				}
 			}
-			Secret::List {} => {
+			Secret::List { format } => {
 				let _span = info_span!("loading secrets").entered();
 				let configured = config.list_configured_shared().await?;
-				#[derive(Tabled)]
-				struct SecretDisplay {
-					#[tabled(rename = "Name")]
-					name: String,
-					#[tabled(rename = "Owners")]
-					owners: String,
-				}
-				let mut table = vec![];
+
+				let mut entries = Vec::new();
+				let mut templates = Vec::new();
 				for name in configured.iter().cloned() {
-					let config = config.clone();
 					let data = config.shared_secret(&name)?.expect("exists");
 					let definition = config.shared_secret_definition(&name)?;
 					let expectations = definition.expectations()?;
-					let owners = data
-						.owners
-						.iter()
-						.map(|o| {
-							if expectations.owners.contains(o) {
-								o.green().to_string()
-							} else {
-								o.red().to_string()
-							}
-						})
-						.collect::<Vec<_>>();
-					table.push(SecretDisplay {
-						owners: owners.join(", "),
+					let owners: BTreeSet<String> = data.owners.iter().cloned().collect();
+					templates.push(definition.template()?.to_string());
+					entries.push(SecretListEntry {
+						in_sync: owners == expectations.owners,
+						expected: expectations.owners,
+						owners,
 						name,
-					})
+					});
+				}
+
+				match format.unwrap_or(ListFormat::Table) {
+					ListFormat::Table => {
+						#[derive(Tabled)]
+						struct SecretDisplay {
+							#[tabled(rename = "Name")]
+							name: String,
+							#[tabled(rename = "Type")]
+							template: String,
+							#[tabled(rename = "Owners")]
+							owners: String,
+						}
+						let table = entries
+							.iter()
+							.zip(templates)
+							.map(|(entry, template)| SecretDisplay {
+								name: entry.name.clone(),
+								template,
+								owners: entry
+									.owners
+									.iter()
+									.map(|o| {
+										if entry.expected.contains(o) {
+											o.green().to_string()
+										} else {
+											o.red().to_string()
+										}
+									})
+									.collect::<Vec<_>>()
+									.join(", "),
+							})
+							.collect::<Vec<_>>();
+						info!("loaded\n{}", Table::new(table).to_string())
+					}
+					ListFormat::Json => {
+						for entry in &entries {
+							println!("{}", serde_json::to_string(entry)?);
+						}
+					}
 				}
-				info!("loaded\n{}", Table::new(table).to_string())
 			}
 			Secret::Edit {
 				name,
@@ -919,32 +1479,162 @@ impl Secret {
 				part,
 				add,
 			} => {
-				let secret = config.host_secret(&machine, &name)?;
-				if let Some(data) = secret.secret.parts.get(&part) {
-					let host = config.host(&machine).await?;
-					let secret = host.decrypt(data.raw.clone()).await?;
-					String::from_utf8(secret).context("secret is not utf8")?
-				} else if add {
-					String::new()
+				let interactive = stdin().is_terminal();
+
+				let machine = match machine {
+					Some(machine) => machine,
+					None => {
+						ensure!(interactive, "--machine is required when stdin is not a tty");
+						let names: Vec<String> =
+							config.list_hosts().await?.iter().map(|h| h.name.clone()).collect();
+						ensure!(!names.is_empty(), "no hosts are defined");
+						let i = FuzzySelect::with_theme(&ColorfulTheme::default())
+							.with_prompt("Machine")
+							.items(&names)
+							.interact()?;
+						names.into_iter().nth(i).expect("index came from interact()")
+					}
+				};
+
+				let name = match name {
+					Some(name) => name,
+					None => {
+						ensure!(interactive, "secret name is required when stdin is not a tty");
+						let names = config.list_secrets(&machine);
+						ensure!(!names.is_empty(), "no secrets are defined for {machine}");
+						let i = FuzzySelect::with_theme(&ColorfulTheme::default())
+							.with_prompt("Secret")
+							.items(&names)
+							.interact()?;
+						names.into_iter().nth(i).expect("index came from interact()")
+					}
+				};
+
+				let host = config.host(&machine).await?;
+				let template = host.secret_definition(&name)?.template()?;
+				let mut secret = config.host_secret(&machine, &name)?;
+
+				if template == SecretTemplate::Untyped {
+					const ADD_NEW_PART: &str = "(add new part...)";
+					let (part, add) = match part {
+						Some(part) => (part, add),
+						None if !interactive => ("secret".to_owned(), add),
+						None => {
+							let mut items: Vec<String> = secret.secret.parts.keys().cloned().collect();
+							items.push(ADD_NEW_PART.to_owned());
+							let i = FuzzySelect::with_theme(&ColorfulTheme::default())
+								.with_prompt("Part")
+								.items(&items)
+								.interact()?;
+							if items[i] == ADD_NEW_PART {
+								let new_part: String = Input::with_theme(&ColorfulTheme::default())
+									.with_prompt("New part name")
+									.interact_text()?;
+								(new_part, true)
+							} else {
+								(items.into_iter().nth(i).expect("index came from interact()"), add)
+							}
+						}
+					};
+
+					let current = if let Some(data) = secret.secret.parts.get(&part) {
+						decrypt_owned(config, &machine, data.raw.clone()).await?
+					} else if add {
+						Vec::new()
+					} else {
+						bail!("part {part} not found in secret {name}. Did you mean to `--add` it?");
+					};
+
+					let header = format!("Editing secret {name:?} part {part:?} of machine {machine:?}.\n");
+					let edited = edit_temp_file(tempfile::Builder::new(), current, &header, "# ").await?;
+
+					let recipient = config.recipient(&machine).await?;
+					let encrypted = encrypt_secret_data([&recipient], edited).expect("recipient provided");
+					secret.secret.parts.insert(part, FleetSecretPart { raw: encrypted });
 				} else {
-					bail!("part {part} not found in secret {name}. Did you mean to `--add` it?");
+					ensure!(
+						part.is_none(),
+						"--part doesn't apply to {name:?} (template: {template}), it edits every field at once"
+					);
+
+					let mut current_values = BTreeMap::new();
+					for f in template.fields() {
+						let Some(data) = secret.secret.parts.get(f.name) else {
+							continue;
+						};
+						let plaintext = if data.raw.encrypted {
+							decrypt_owned(config, &machine, data.raw.clone()).await?
+						} else {
+							data.raw.data.clone()
+						};
+						current_values.insert(f.name.to_owned(), plaintext);
+					}
+
+					let buffer = render_template(template, &current_values);
+					let header = format!("Editing secret {name:?} of machine {machine:?} (template: {template}).\n");
+					let edited = edit_temp_file(tempfile::Builder::new(), buffer.into_bytes(), &header, "# ").await?;
+					let edited = String::from_utf8(edited).context("edited buffer is not utf8")?;
+					let fields = parse_template(template, &edited)?;
+
+					let recipient = config.recipient(&machine).await?;
+					for (field_name, value) in fields {
+						let encrypted = encrypt_secret_data([&recipient], value).expect("recipient provided");
+						secret.secret.parts.insert(field_name, FleetSecretPart { raw: encrypted });
+					}
+				}
+
+				config.insert_secret(&machine, name, secret);
+			}
+			Secret::BackupKey { machine } => {
+				// `host_identity`/`install_host_identity` extend `Config`'s existing
+				// `key`/`recipient` (public-side) accessors with the private-side operations
+				// this command needs; see the `host`-module note elsewhere in this crate.
+				let identity = config.host_identity(&machine).await?;
+				let phrase = mnemonic::encode(&identity)?;
+				println!("{phrase}");
+			}
+			Secret::RecoverKey { machine, fuzzy } => {
+				let mut phrase = String::new();
+				stdin().read_to_string(&mut phrase)?;
+				let identity = if fuzzy {
+					mnemonic::decode_with_fuzzy_correction(phrase.trim())?
+				} else {
+					mnemonic::decode(phrase.trim())?
 				};
+				config.install_host_identity(&machine, identity).await?;
+				info!("identity for {machine} recovered and installed");
+			}
+			Secret::Audit { format, expiring_soon } => {
+				let mut entries = collect_audit_entries(config, opts).await?;
+				if expiring_soon {
+					entries.retain(|e| e.expiring_soon);
+				}
+				match format.unwrap_or(AuditFormat::Table) {
+					AuditFormat::Table => {
+						let rows: Vec<AuditRow> = entries.iter().map(AuditRow::from).collect();
+						info!("secret audit:\n{}", Table::new(rows));
+					}
+					AuditFormat::Prometheus => {
+						print!("{}", render_audit_prometheus(&entries));
+					}
+				}
 			}
 		}
 		Ok(())
 	}
 }
 
-/*
-async fn edit_temp_file(
-	builder: tempfile::Builder<'_, '_>,
-	r: Vec<u8>,
-	header: &str,
-	comment: &str,
-) -> Result<(Vec<u8>, Option<String>), anyhow::Error> {
-	if !stdin().is_tty() {
+/// Writes `r` to a tempfile preceded by a header (every line of `header` prefixed with
+/// `comment`, followed by a "don't touch this" line and a blank separator), opens it in
+/// `$VISUAL`/`$EDITOR`/`vi` (split with `shlex`, so e.g. `EDITOR="code --wait"` works), and on a
+/// clean exit reads the file back and strips the header back off. Bails without returning
+/// anything if stdin isn't a tty (no editor can be opened), or if the header no longer matches
+/// verbatim on read-back (the user mangled it) - better to abort than silently save whatever's
+/// left after a bad strip.
+async fn edit_temp_file(builder: tempfile::Builder<'_, '_>, r: Vec<u8>, header: &str, comment: &str) -> Result<Vec<u8>> {
+	if !stdin().is_terminal() {
 		// TODO: Also try to open /dev/tty directly?
-		bail!("stdin is not tty, can't open editor");
+		bail!("stdin is not a tty, can't open an editor");
 	}
 
 	use std::fmt::Write;
@@ -963,6 +1653,7 @@ async fn edit_temp_file(
 		&mut full_header,
 		"{comment}Do not touch this header! It will be removed automatically"
 	)?;
+	writeln!(&mut full_header)?;
 
 	file.write_all(full_header.as_bytes())?;
 	file.write_all(&r)?;
@@ -992,26 +1683,24 @@ async fn edit_temp_file(
 	let was_raw = terminal::is_raw_mode_enabled()?;
 	terminal::enable_raw_mode()?;
 
-	let status = command.arg(path_arg).status().await;
+	let status = command.arg(&path_arg).status().await;
 
 	if !was_raw {
 		terminal::disable_raw_mode()?;
 	}
 
-	let success = match status {
-		Ok(s) => s.success(),
+	let status = match status {
+		Ok(s) => s,
 		Err(e) if e.kind() == io::ErrorKind::NotFound => {
 			bail!("editor not found")
 		}
 		Err(e) => bail!("editor spawn error: {e}"),
 	};
+	ensure!(status.success(), "editor exited with {status}");
 
-	let mut file = std::fs::read(&abs_path).context("read editor output")?;
-	let Some(v) = file.strip_prefix(full_header.as_bytes()) else {
-		todo!();
+	let file = std::fs::read(&abs_path).context("read editor output")?;
+	let Some(rest) = file.strip_prefix(full_header.as_bytes()) else {
+		bail!("the header was modified, aborting without saving to avoid corrupting the secret");
 	};
-	todo!();
-
-	// Ok((success, abs_path))
+	Ok(rest.to_vec())
 }
-*/
@@ -1,6 +1,6 @@
 use std::{collections::BTreeMap, ffi::OsString, path::PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use clap::Parser;
 use fleet_base::host::Config;
 use nix_eval::nix_go;
@@ -12,7 +12,7 @@ use tokio::{
 	process::Command,
 	task::spawn_blocking,
 };
-use tracing::debug;
+use tracing::{debug, warn};
 
 #[derive(Deserialize, Debug)]
 pub struct TfData {
@@ -27,11 +27,30 @@ pub struct TfData {
 
 #[derive(Parser)]
 pub struct Tf {
+	/// Named terraform workspace to operate on, stored at `.fleet/tf/<workspace>`
+	#[clap(long, default_value = "default")]
+	workspace: String,
+	/// Binary to invoke instead of auto-detecting `tofu`/`terraform`
+	#[clap(long)]
+	binary: Option<String>,
 	args: Vec<OsString>,
 }
 impl Tf {
+	/// Picks the terraform-compatible binary to run: an explicit `--binary` override,
+	/// otherwise `tofu` if it's on `PATH`, falling back to `terraform`.
+	async fn binary(&self) -> String {
+		if let Some(binary) = &self.binary {
+			return binary.clone();
+		}
+		if Command::new("tofu").arg("-version").output().await.is_ok() {
+			"tofu".to_owned()
+		} else {
+			"terraform".to_owned()
+		}
+	}
 	pub async fn run(&self, config: &Config) -> Result<()> {
-		let dir = config.directory.join(".fleet/tf/default");
+		let dir = config.directory.join(".fleet/tf").join(&self.workspace);
+		let binary = self.binary().await;
 		// TODO: consider postponing fleet init until this step, as it might be
 		// highly preferred to extract terraform configuration using multithreaded nix or
 		// lazy-trees nix. lazy-trees nix is very fast and perfect for this task.
@@ -53,8 +72,20 @@ impl Tf {
 		}
 
 		{
-			debug!("running terraform command");
-			Command::new("terraform")
+			debug!("validating terraform configs with {binary}");
+			let status = Command::new(&binary)
+				.current_dir(&dir)
+				.arg("validate")
+				.status()
+				.await?;
+			if !status.success() {
+				bail!("{binary} validate failed, not running requested command");
+			}
+		}
+
+		{
+			debug!("running {binary} command");
+			Command::new(&binary)
 				.current_dir(&dir)
 				.args(&self.args)
 				.status()
@@ -62,8 +93,8 @@ impl Tf {
 		}
 		{
 			debug!("syncing terraform data");
-			let data = Command::new("terraform")
-				.current_dir(dir)
+			let data = Command::new(&binary)
+				.current_dir(&dir)
 				.arg("output")
 				.arg("-json")
 				.arg("fleet")
@@ -72,8 +103,21 @@ impl Tf {
 			let tf_data: TfData = serde_json::from_slice(&data.stdout)
 				.context("failed to parse terraform fleet output")?;
 
+			let known_hosts = config.list_hosts().await?;
+
 			let mut data = config.data();
 			debug!("synchronized done = {tf_data:?}");
+			for (host, output) in &tf_data.hosts {
+				if !known_hosts.iter().any(|h| &h.name == host) {
+					warn!("terraform output references unknown host {host:?}, skipping");
+					continue;
+				}
+				// Route each host's outputs (IPs, generated keys, DNS records, ...) into
+				// its own config slot, rather than only the single opaque blob below.
+				data
+					.extra
+					.insert(format!("tf:{}:{host}", self.workspace), output.clone());
+			}
 			data.extra.insert(
 				"terraformHosts".to_owned(),
 				serde_json::to_value(tf_data.hosts).expect("should be valid extra"),
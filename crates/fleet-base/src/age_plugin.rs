@@ -0,0 +1,83 @@
+//! Support for hardware-backed age identities/recipients exposed via age plugins
+//! (`age-plugin-yubikey`, `age-plugin-fido2-hmac`, `age-plugin-tpm`, ...), so a host's
+//! decryption key can live on a YubiKey/FIDO2 token/TPM rather than a file on disk.
+//!
+//! This module recognizes plugin-backed recipient/identity stanzas and runs the corresponding
+//! `age-plugin-<name>` binary over a host's existing remote command channel
+//! ([`crate::host::ConfigHost::cmd`]). `cmds/fleet/src/cmds/secrets/mod.rs`'s `decrypt_owned`
+//! and `maybe_regenerate_shared_secret`'s reencryption loop call [`run_plugin_identity`]
+//! whenever an owner's registered key is plugin-shaped, instead of going through
+//! `ConfigHost::decrypt`/`reencrypt` (which only know the regular identity-file path). As noted
+//! on [`run_plugin_identity`], that still only covers plugins that work as a one-shot filter
+//! over stdin/stdout; the full bidirectional age-plugin protocol isn't implemented here.
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::host::ConfigHost;
+
+/// An age recipient or identity line delegated to an external `age-plugin-<name>` binary, per
+/// the age plugin spec (<https://c2sp.org/age-plugin>) — e.g. `age1yubikey1qqqq...` (recipient)
+/// or `AGE-PLUGIN-YUBIKEY-1QQQQ...` (identity).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginStanza {
+	/// Plugin name as encoded in the recipient/identity (`yubikey`, `fido2-hmac`, `tpm`, ...);
+	/// the binary invoked for it is `age-plugin-{name}`.
+	pub plugin_name: String,
+	/// The full recipient or identity line, passed to the plugin binary as-is.
+	pub line: String,
+}
+
+/// Recognizes an age recipient or identity line backed by an age plugin, leaving plain
+/// `age1...`/`AGE-SECRET-KEY-1...` lines (handled by the existing non-plugin path) alone.
+pub fn parse_plugin_stanza(line: &str) -> Option<PluginStanza> {
+	let line = line.trim();
+	if let Some(rest) = line.strip_prefix("age1") {
+		let (name, _) = rest.split_once('1')?;
+		if name.is_empty() {
+			return None;
+		}
+		return Some(PluginStanza {
+			plugin_name: name.to_owned(),
+			line: line.to_owned(),
+		});
+	}
+	if let Some(rest) = line.strip_prefix("AGE-PLUGIN-") {
+		let (name, _) = rest.split_once("-1")?;
+		if name.is_empty() {
+			return None;
+		}
+		return Some(PluginStanza {
+			plugin_name: name.to_lowercase(),
+			line: line.to_owned(),
+		});
+	}
+	None
+}
+
+/// Runs `stanza`'s plugin binary (`age-plugin-<name>`) on `host` to perform the private-key
+/// operation (identity decryption, or producing a recipient from an identity), feeding it
+/// `input` on stdin and returning its stdout.
+///
+/// This only drives the plugin binary as a one-shot filter (`age-plugin-<name> --age-plugin`),
+/// which covers the common case of a plugin that speaks the identity/recipient sub-protocol
+/// without additional interactive round-trips (e.g. a PIN prompt relayed over stderr/tty
+/// rather than the stdin/stdout state machine); a plugin that requires the full bidirectional
+/// phase-based exchange from the age-plugin spec isn't supported by this wrapper yet.
+pub async fn run_plugin_identity(host: &ConfigHost, stanza: &PluginStanza, input: &[u8]) -> Result<Vec<u8>> {
+	let binary = format!("age-plugin-{}", stanza.plugin_name);
+	let mut cmd = host
+		.cmd(&binary)
+		.await
+		.with_context(|| format!("{binary} not available on {}", host.name))?;
+	cmd.arg("--age-plugin");
+	cmd.stdin(input.to_vec());
+	cmd.run_bytes()
+		.await
+		.with_context(|| format!("running {binary} for {:?}", stanza.line))
+}
+
+/// Resolves a single key/recipient line to its plugin, if any, bailing with a clear error when
+/// the line looks plugin-shaped but names no recognizable plugin (e.g. a typo'd prefix).
+pub fn require_plugin_stanza(line: &str) -> Result<PluginStanza> {
+	parse_plugin_stanza(line).ok_or_else(|| anyhow!("{line:?} is not a recognized age plugin recipient/identity"))
+}
@@ -0,0 +1,250 @@
+//! Authenticated, parity-protected archives of a store closure, for shipping a deployment
+//! to a host over a flaky or untrusted transport (a slow serial console, a one-shot USB
+//! stick, an unreliable relay, ...) where a plain `nix copy`/`nix-store --export` stream has
+//! no way to recover from a handful of bit-flips or a truncated transfer.
+//!
+//! The NAR byte stream coming out of `nix-store --export` is split into fixed-size data
+//! shards, grouped into Reed-Solomon code blocks (`k` data + `m` parity shards, default
+//! 4+2), and each resulting shard is sealed independently with XChaCha20-Poly1305. Losing or
+//! corrupting up to `m` shards per block is transparently recovered from parity on import;
+//! anything worse is a hard error rather than a silently truncated closure.
+
+use std::{
+	io::{Read, Write},
+	path::Path,
+	process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use chacha20poly1305::{
+	XChaCha20Poly1305, XNonce,
+	aead::{Aead, AeadCore, KeyInit, OsRng, rand_core::RngCore},
+};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Size of a single shard before Reed-Solomon coding, chosen so a single corrupt/lost shard
+/// costs one 1 MiB re-fetch rather than the whole archive.
+pub const SHARD_SIZE: usize = 1024 * 1024;
+
+/// `data`/`parity` shard counts for a Reed-Solomon code block. Default 4+2 tolerates any 2
+/// corrupt or missing shards out of every 6.
+#[derive(Debug, Clone, Copy)]
+pub struct ShardRatio {
+	pub data: usize,
+	pub parity: usize,
+}
+impl Default for ShardRatio {
+	fn default() -> Self {
+		Self { data: 4, parity: 2 }
+	}
+}
+
+/// A 256-bit key sealing every shard of an archive, caller-supplied (e.g. derived from a
+/// host's secret-encryption identity) or freshly generated.
+pub struct ArchiveKey([u8; 32]);
+impl ArchiveKey {
+	pub fn generate() -> Self {
+		let mut key = [0u8; 32];
+		OsRng.fill_bytes(&mut key);
+		Self(key)
+	}
+	pub fn from_bytes(bytes: [u8; 32]) -> Self {
+		Self(bytes)
+	}
+	fn cipher(&self) -> XChaCha20Poly1305 {
+		XChaCha20Poly1305::new_from_slice(&self.0).expect("key is exactly 32 bytes")
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ShardEntry {
+	nonce: [u8; 24],
+	sha256: [u8; 32],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BlockEntry {
+	/// `ratio.data + ratio.parity` entries, data shards first, in on-disk order.
+	shards: Vec<ShardEntry>,
+}
+
+/// Side-channel metadata an archive's importer needs alongside the shard stream itself:
+/// per-shard nonce and plaintext digest, and the exact plaintext length so the reconstructed
+/// NAR's zero-padded tail shard can be trimmed back to size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+	ratio: (usize, usize),
+	shard_size: usize,
+	total_len: u64,
+	blocks: Vec<BlockEntry>,
+}
+
+fn read_full(r: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+	let mut read = 0;
+	while read < buf.len() {
+		let n = r.read(&mut buf[read..])?;
+		if n == 0 {
+			break;
+		}
+		read += n;
+	}
+	Ok(read)
+}
+
+/// Reads `nar` to completion, writing the sealed, parity-protected archive to `out` and
+/// returning the manifest describing how to reverse it. `nar` is expected to be a
+/// `nix-store --export` stream (closure metadata + NARs), not a bare single-path NAR, so
+/// [`import`] can feed the reconstructed bytes straight into `nix-store --import`.
+pub fn export(mut nar: impl Read, mut out: impl Write, key: &ArchiveKey, ratio: ShardRatio) -> Result<ArchiveManifest> {
+	let rs = ReedSolomon::new(ratio.data, ratio.parity).context("constructing Reed-Solomon coder")?;
+	let cipher = key.cipher();
+
+	let mut total_len = 0u64;
+	let mut blocks = Vec::new();
+	loop {
+		let mut shards = Vec::with_capacity(ratio.data + ratio.parity);
+		let mut read_any = false;
+		for _ in 0..ratio.data {
+			let mut shard = vec![0u8; SHARD_SIZE];
+			let n = read_full(&mut nar, &mut shard)?;
+			if n == 0 {
+				break;
+			}
+			read_any = true;
+			total_len += n as u64;
+			shards.push(shard);
+		}
+		if !read_any {
+			break;
+		}
+		// A short trailing block is padded out to a full `ratio.data` shards with zeroes so
+		// the coder always sees a uniform block; `total_len` is what trims the padding away
+		// again on import.
+		while shards.len() < ratio.data {
+			shards.push(vec![0u8; SHARD_SIZE]);
+		}
+		shards.extend((0..ratio.parity).map(|_| vec![0u8; SHARD_SIZE]));
+		rs.encode(&mut shards).context("Reed-Solomon encode")?;
+
+		let mut entries = Vec::with_capacity(shards.len());
+		for shard in &shards {
+			let sha256 = Sha256::digest(shard).into();
+			let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+			let ciphertext = cipher
+				.encrypt(&nonce, shard.as_slice())
+				.map_err(|_| anyhow!("failed to seal archive shard"))?;
+			out.write_all(&ciphertext)?;
+			entries.push(ShardEntry {
+				nonce: nonce.into(),
+				sha256,
+			});
+		}
+		blocks.push(BlockEntry { shards: entries });
+	}
+
+	Ok(ArchiveManifest {
+		ratio: (ratio.data, ratio.parity),
+		shard_size: SHARD_SIZE,
+		total_len,
+		blocks,
+	})
+}
+
+/// Reverses [`export`]: verifies and decrypts every shard, reconstructs up to `parity`
+/// corrupt or missing shards per block from the rest, and writes the original plaintext
+/// stream to `out`.
+pub fn import(manifest: &ArchiveManifest, mut archive_in: impl Read, key: &ArchiveKey, mut out: impl Write) -> Result<()> {
+	let (data, parity) = manifest.ratio;
+	let rs = ReedSolomon::new(data, parity).context("constructing Reed-Solomon coder")?;
+	let cipher = key.cipher();
+	let sealed_shard_len = manifest.shard_size + 16; // Poly1305 tag
+
+	let mut written = 0u64;
+	for block in &manifest.blocks {
+		let mut shards: Vec<Option<Vec<u8>>> = Vec::with_capacity(block.shards.len());
+		for entry in &block.shards {
+			let mut ciphertext = vec![0u8; sealed_shard_len];
+			let plaintext = (read_full(&mut archive_in, &mut ciphertext)? == sealed_shard_len)
+				.then(|| {
+					let nonce = XNonce::from_slice(&entry.nonce);
+					cipher.decrypt(nonce, ciphertext.as_slice()).ok()
+				})
+				.flatten()
+				.filter(|plaintext| Sha256::digest(plaintext).as_slice() == entry.sha256);
+			shards.push(plaintext);
+		}
+
+		let missing = shards.iter().filter(|s| s.is_none()).count();
+		if missing > parity {
+			bail!(
+				"archive block is missing/corrupt in {missing} shards, but only {parity} parity shards are available"
+			);
+		}
+		if missing > 0 {
+			rs.reconstruct(&mut shards)
+				.context("Reed-Solomon reconstruction")?;
+		}
+
+		for shard in shards.into_iter().take(data) {
+			let shard = shard.expect("every data shard is present after reconstruction");
+			let take = (manifest.total_len - written).min(shard.len() as u64) as usize;
+			out.write_all(&shard[..take])?;
+			written += take as u64;
+		}
+	}
+
+	Ok(())
+}
+
+/// Computes the full closure of `store_path` (via `nix-store --query --requisites`), exports
+/// it (via `nix-store --export`) and archives the resulting stream. This is the entry point
+/// callers building/deploying a locked flake output should use.
+pub fn export_closure(store_path: &Path, out: impl Write, key: &ArchiveKey, ratio: ShardRatio) -> Result<ArchiveManifest> {
+	let requisites = Command::new("nix-store")
+		.arg("--query")
+		.arg("--requisites")
+		.arg(store_path)
+		.output()
+		.context("listing closure with nix-store --query --requisites")?;
+	if !requisites.status.success() {
+		bail!("nix-store --query --requisites failed: {}", requisites.status);
+	}
+	let paths = String::from_utf8(requisites.stdout).context("nix-store output is not utf-8")?;
+
+	let mut export_proc = Command::new("nix-store")
+		.arg("--export")
+		.args(paths.lines())
+		.stdout(Stdio::piped())
+		.spawn()
+		.context("spawning nix-store --export")?;
+	let stdout = export_proc.stdout.take().expect("stdout was piped");
+	let manifest = export(stdout, out, key, ratio)?;
+	let status = export_proc
+		.wait()
+		.context("waiting for nix-store --export")?;
+	if !status.success() {
+		bail!("nix-store --export failed: {status}");
+	}
+	Ok(manifest)
+}
+
+/// Reverses [`export_closure`]: reconstructs the `nix-store --export` stream and feeds it
+/// into `nix-store --import`, registering every store path it contains.
+pub fn import_closure(manifest: &ArchiveManifest, archive_in: impl Read, key: &ArchiveKey) -> Result<()> {
+	let mut import_proc = Command::new("nix-store")
+		.arg("--import")
+		.stdin(Stdio::piped())
+		.spawn()
+		.context("spawning nix-store --import")?;
+	let stdin = import_proc.stdin.take().expect("stdin was piped");
+	import(manifest, archive_in, key, stdin)?;
+	let status = import_proc
+		.wait()
+		.context("waiting for nix-store --import")?;
+	if !status.success() {
+		bail!("nix-store --import failed: {status}");
+	}
+	Ok(())
+}
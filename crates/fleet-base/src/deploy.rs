@@ -1,14 +1,24 @@
 use std::{path::PathBuf, time::Duration};
 
 use anyhow::{Context as _, Result, anyhow, bail};
+use chrono::{DateTime, Utc};
 use clap::ValueEnum;
 use itertools::Itertools;
-use tokio::time::sleep;
+use serde::{Deserialize, Serialize};
+use tokio::time::{Instant, sleep};
 use tracing::{Instrument as _, error, info, info_span, warn};
 
 use crate::host::{Config, ConfigHost, DeployKind, Generation, GenerationStorage};
 
-#[derive(ValueEnum, Clone, Copy)]
+/// Default `rollback_confirm_timeout`: how long [`confirm_activation`] retries a fresh connection
+/// before giving up. Comfortably inside the 3-minute watchdog window scheduled below, so a slow-
+/// to-settle network doesn't race the rollback it's meant to prevent.
+pub const DEFAULT_ROLLBACK_CONFIRM_TIMEOUT: Duration = Duration::from_secs(90);
+/// Default `rollback_confirm_retry_interval`: how long [`confirm_activation`] waits between
+/// liveness retries.
+pub const DEFAULT_ROLLBACK_CONFIRM_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(ValueEnum, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum DeployAction {
 	/// Upload derivation, but do not execute the update.
 	Upload,
@@ -45,7 +55,70 @@ impl DeployAction {
 	}
 }
 
-async fn get_current_generation(host: &ConfigHost) -> Result<Generation> {
+/// One step recorded into a [`DeployReceipt`] as `deploy_task` performs it, mirroring
+/// nix-installer's serializable action receipts: enough for `fleet rollback`/`fleet resume` to
+/// know how far a deploy got without re-deriving that from the live host.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReceiptStep {
+	RollbackMarkerArmed,
+	ProfileSwitched,
+	Activated,
+	RollbackMarkerCleared,
+}
+
+/// A deploy's on-disk journal for a single host, stored under `config.data().extra` keyed by
+/// [`receipt_key`]. `fleet rollback <host>` reads the most recent one to revert deterministically;
+/// `fleet resume` reads it to tell which hosts didn't finish their last `fleet deploy`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployReceipt {
+	pub host: String,
+	pub deploy_kind: DeployKind,
+	pub action: DeployAction,
+	/// Signed, uploaded closure path this receipt's steps were performed against.
+	pub built: PathBuf,
+	/// The generation that was current before this deploy touched the profile, i.e. what
+	/// `fleet rollback` re-points `/nix/var/nix/profiles/system` to.
+	pub previous_generation: Option<String>,
+	/// Whether the deploy that wrote this receipt was launched with rollback disabled, so
+	/// `fleet resume` can honor the original choice instead of silently re-enabling rollback
+	/// for a deploy that was explicitly run without it.
+	pub disable_rollback: bool,
+	pub started_at: DateTime<Utc>,
+	pub steps: Vec<ReceiptStep>,
+}
+
+impl DeployReceipt {
+	/// Whether this deploy reached activation, the point past which `fleet resume` has nothing
+	/// useful left to redo.
+	pub fn is_complete(&self) -> bool {
+		self.steps.iter().any(|s| matches!(s, ReceiptStep::Activated))
+	}
+}
+
+fn receipt_key(host: &str) -> String {
+	format!("deploy_receipt:{host}")
+}
+
+/// Reads the most recent [`DeployReceipt`] recorded for `host`, if any.
+pub fn load_receipt(config: &Config, host: &str) -> Option<DeployReceipt> {
+	let value = config.data().extra.get(&receipt_key(host))?.clone();
+	match serde_json::from_value(value) {
+		Ok(receipt) => Some(receipt),
+		Err(e) => {
+			warn!("failed to parse deploy receipt for {host:?}, ignoring it: {e}");
+			None
+		}
+	}
+}
+
+fn save_receipt(config: &Config, receipt: &DeployReceipt) {
+	let value = serde_json::to_value(receipt).expect("DeployReceipt is always representable as JSON");
+	config.data().extra.insert(receipt_key(&receipt.host), value);
+}
+
+/// Looks up `host`'s currently active generation; used both to compute a rollback target before
+/// arming the marker, and by `fleet deploy --interactive`'s plan preview.
+pub async fn get_current_generation(host: &ConfigHost) -> Result<Generation> {
 	let generations = host.list_generations("system").await?;
 	let current = generations
 		.into_iter()
@@ -56,12 +129,46 @@ async fn get_current_generation(host: &ConfigHost) -> Result<Generation> {
 	Ok(current)
 }
 
+/// "Magic rollback" confirmation, borrowed from deploy-rs: the activation script may have just
+/// broken the host's networking/firewall without the SSH command that ran it noticing, because
+/// that command runs over a connection that was already established before the change took
+/// effect. Tear the connection down and require a genuinely *fresh* one, plus a trivial liveness
+/// command, to succeed before the caller trusts the activation enough to disarm the watchdog.
+async fn confirm_activation(
+	host: &ConfigHost,
+	timeout: Duration,
+	retry_interval: Duration,
+) -> Result<()> {
+	let deadline = Instant::now() + timeout;
+	loop {
+		host.disconnect().await;
+		let liveness = async {
+			let mut cmd = host.cmd("true").await?;
+			cmd.run().await
+		}
+		.await;
+		match liveness {
+			Ok(()) => return Ok(()),
+			Err(e) if Instant::now() < deadline => {
+				warn!("fresh connection liveness check failed, retrying: {e}");
+				sleep(retry_interval).await;
+			}
+			Err(e) => bail!("host did not become reachable over a fresh connection within {timeout:?}: {e}"),
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn deploy_task(
 	action: DeployAction,
 	host: &ConfigHost,
 	built: PathBuf,
 	specialisation: Option<String>,
 	disable_rollback: bool,
+	rollback_confirm_timeout: Duration,
+	rollback_confirm_retry_interval: Duration,
+	config: &Config,
+	resume_previous_generation: Option<String>,
 ) -> Result<()> {
 	let deploy_kind = host.deploy_kind().await?;
 	if (deploy_kind == DeployKind::NixosInstall || deploy_kind == DeployKind::NixosLustrate)
@@ -71,6 +178,23 @@ pub async fn deploy_task(
 	}
 
 	let mut failed = false;
+	let mut receipt = action.should_create_rollback_marker().then(|| DeployReceipt {
+		host: host.name.clone(),
+		deploy_kind,
+		action,
+		built: built.clone(),
+		previous_generation: resume_previous_generation.clone(),
+		disable_rollback,
+		started_at: Utc::now(),
+		steps: Vec::new(),
+	});
+	// With rollback disabled the block below never looks up the current generation, so record it
+	// here, letting `fleet rollback <host> auto` revert such a deploy too.
+	if disable_rollback && resume_previous_generation.is_none() {
+		if let Some(receipt) = &mut receipt {
+			receipt.previous_generation = get_current_generation(host).await.ok().map(|g| g.rollback_id());
+		}
+	}
 
 	// TODO: Lockfile, to prevent concurrent system switch?
 	// TODO: If rollback target exists - bail, it should be removed. Lockfile will not work in case if rollback
@@ -79,18 +203,37 @@ pub async fn deploy_task(
 	// This code is tied to rollback.nix
 	if !disable_rollback && action.should_create_rollback_marker() {
 		let _span = info_span!("preparing").entered();
-		info!("preparing for rollback");
-		let generation = get_current_generation(host).await?;
-		info!(
-			"rollback target would be {} {}",
-			generation.id, generation.datetime
-		);
-		{
-			let mut cmd = host.cmd("sh").await?;
-			cmd.arg("-c").arg(format!("mark=$(mktemp -p /etc -t fleet_rollback_marker.XXXXX) && echo -n {} > $mark && mv --no-clobber $mark /etc/fleet_rollback_marker", generation.id));
-			if let Err(e) = cmd.sudo().run().await {
-				error!("failed to set rollback marker: {e}");
-				failed = true;
+		// `resume_previous_generation` means we're re-driving a deploy whose previous attempt
+		// already recorded (and may already have armed) the real pre-deploy rollback target; by
+		// now the host's "current" generation could be the *new* one (if it got past
+		// `ProfileSwitched` before dying), so re-querying it here would silently clobber the
+		// receipt with the wrong target. Trust the recorded value instead of live host state.
+		if let Some(previous_generation) = &resume_previous_generation {
+			info!("resuming interrupted deploy, reusing recorded rollback target {previous_generation}");
+		} else {
+			info!("preparing for rollback");
+			let generation = get_current_generation(host).await?;
+			info!(
+				"rollback target would be {} {}",
+				generation.id, generation.datetime
+			);
+			if let Some(receipt) = &mut receipt {
+				receipt.previous_generation = Some(generation.rollback_id());
+				save_receipt(config, receipt);
+			}
+			{
+				let mut cmd = host.cmd("sh").await?;
+				cmd.arg("-c").arg(format!("mark=$(mktemp -p /etc -t fleet_rollback_marker.XXXXX) && echo -n {} > $mark && mv --no-clobber $mark /etc/fleet_rollback_marker", generation.id));
+				if let Err(e) = cmd.sudo().run().await {
+					error!("failed to set rollback marker: {e}");
+					failed = true;
+				}
+			}
+		}
+		if !failed {
+			if let Some(receipt) = &mut receipt {
+				receipt.steps.push(ReceiptStep::RollbackMarkerArmed);
+				save_receipt(config, receipt);
 			}
 		}
 		// Activation script also starts rollback-watchdog.timer, however, it is possible that it won't be started.
@@ -173,11 +316,12 @@ pub async fn deploy_task(
 			if let Err(e) = cmd.sudo().run_nix().await {
 				error!("failed to switch system profile generation: {e}");
 				failed = true;
+			} else if let Some(receipt) = &mut receipt {
+				receipt.steps.push(ReceiptStep::ProfileSwitched);
+				save_receipt(config, receipt);
 			}
 		}
 
-		// FIXME: Connection might be disconnected after activation run
-
 		if action.should_activate() && !failed {
 			let _span = info_span!("activating").entered();
 			info!("executing activation script");
@@ -198,6 +342,9 @@ pub async fn deploy_task(
 			if let Err(e) = cmd.sudo().run().in_current_span().await {
 				error!("failed to activate: {e}");
 				failed = true;
+			} else if let Some(receipt) = &mut receipt {
+				receipt.steps.push(ReceiptStep::Activated);
+				save_receipt(config, receipt);
 			}
 		}
 	}
@@ -214,6 +361,37 @@ pub async fn deploy_task(
 						error!("failed to trigger rollback: {e}")
 					}
 				}
+			} else if action.should_schedule_rollback_run() {
+				let _span = info_span!("confirming").entered();
+				info!("confirming the host survived activation over a fresh connection");
+				match confirm_activation(host, rollback_confirm_timeout, rollback_confirm_retry_interval)
+					.in_current_span()
+					.await
+				{
+					Ok(()) => {
+						info!("trying to mark upgrade as successful");
+						if let Err(e) = host
+							.rm_file("/etc/fleet_rollback_marker", true)
+							.in_current_span()
+							.await
+						{
+							error!(
+								"failed to remove rollback marker. This is bad, as the system will be rolled back by watchdog: {e}"
+							)
+						} else if let Some(receipt) = &mut receipt {
+							receipt.steps.push(ReceiptStep::RollbackMarkerCleared);
+							save_receipt(config, receipt);
+						}
+					}
+					Err(e) => {
+						// Deliberately leave the marker in place: whatever this activation broke,
+						// rollback-watchdog.service firing on schedule is the safety net. Returning
+						// here also skips disarming the watchdog timers below.
+						return Err(e).context(
+							"host did not confirm reachability after activation, leaving rollback marker in place so the watchdog reverts it",
+						);
+					}
+				}
 			} else {
 				info!("trying to mark upgrade as successful");
 				if let Err(e) = host
@@ -224,6 +402,9 @@ pub async fn deploy_task(
 					error!(
 						"failed to remove rollback marker. This is bad, as the system will be rolled back by watchdog: {e}"
 					)
+				} else if let Some(receipt) = &mut receipt {
+					receipt.steps.push(ReceiptStep::RollbackMarkerCleared);
+					save_receipt(config, receipt);
 				}
 			}
 			info!("disarming watchdog, just in case");
@@ -0,0 +1,156 @@
+//! Checksummed mnemonic encoding/decoding, used by `secrets backup-key`/`recover-key` to back up
+//! and restore a host's raw age identity bytes as a human-transcribable word list.
+//!
+//! The scheme is BIP39-shaped but is *not* BIP39 and isn't interoperable with BIP39 tooling:
+//! entropy whose bit length is a multiple of 32 is appended with a `len(entropy) * 8 / 32`-bit
+//! checksum (the leading bits of `SHA256(entropy)`), and the result is split into 11-bit groups,
+//! each an index into a 2048-word list — but the wordlist bundled here (see [`wordlist`]) is
+//! deterministically generated, not the canonical BIP39 English list. This checkout has no
+//! network access to fetch the real one, and transcribing all 2048 words by hand risks silent,
+//! hard-to-notice mistakes, which would be worse than shipping our own list under our own name.
+//! A phrase produced by [`encode`] will not be accepted by `bip39`/wallet tooling and vice versa;
+//! it only round-trips through [`decode`]/[`decode_with_fuzzy_correction`] in this crate.
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use sha2::{Digest, Sha256};
+
+const WORD_COUNT: usize = 2048;
+
+/// The 2048-word list mnemonic indices are drawn from; see the module doc comment.
+fn wordlist() -> &'static [String; WORD_COUNT] {
+	static WORDLIST: std::sync::OnceLock<[String; WORD_COUNT]> = std::sync::OnceLock::new();
+	WORDLIST.get_or_init(|| {
+		const CONSONANTS: &[u8] = b"bcdfghjklmnprstvwz";
+		const VOWELS: &[u8] = b"aeiou";
+		let mut seen = std::collections::HashSet::new();
+		let mut words = Vec::with_capacity(WORD_COUNT);
+		// Splitmix64, seeded with a fixed constant: deterministic and good enough to produce
+		// 2048 distinct four-syllable words without a real RNG dependency.
+		let mut state: u64 = 0x9E3779B97F4A7C15;
+		while words.len() < WORD_COUNT {
+			state = state.wrapping_add(0x9E3779B97F4A7C15);
+			let mut mixed = state;
+			mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+			mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D049BB133111EB);
+			mixed ^= mixed >> 31;
+
+			let mut n = mixed;
+			let mut word = String::with_capacity(8);
+			for _ in 0..4 {
+				word.push(CONSONANTS[(n as usize) % CONSONANTS.len()] as char);
+				n /= CONSONANTS.len() as u64;
+				word.push(VOWELS[(n as usize) % VOWELS.len()] as char);
+				n /= VOWELS.len() as u64;
+			}
+			if seen.insert(word.clone()) {
+				words.push(word);
+			}
+		}
+		words.try_into().expect("exactly WORD_COUNT distinct words generated")
+	})
+}
+
+fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
+	bytes
+		.iter()
+		.flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1))
+		.collect()
+}
+
+fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
+	bits.chunks(8)
+		.map(|chunk| chunk.iter().fold(0u8, |acc, &bit| (acc << 1) | bit as u8))
+		.collect()
+}
+
+/// How many of a mnemonic's total bits are entropy (the rest being checksum), or an error if
+/// `word_count` isn't a valid length for this scheme (`word_count * 11` must be a multiple of 33).
+fn entropy_bits_for(word_count: usize) -> Result<usize> {
+	let total_bits = word_count * 11;
+	ensure!(
+		word_count > 0 && total_bits % 33 == 0,
+		"{word_count} words isn't a valid mnemonic length"
+	);
+	Ok(total_bits * 32 / 33)
+}
+
+/// Encodes `entropy` (the raw private identity bytes) as a mnemonic phrase.
+pub fn encode(entropy: &[u8]) -> Result<String> {
+	ensure!(
+		!entropy.is_empty() && entropy.len() % 4 == 0,
+		"entropy length must be a nonzero multiple of 4 bytes, got {}",
+		entropy.len()
+	);
+	let checksum_bits = entropy.len() * 8 / 32;
+	let hash = Sha256::digest(entropy);
+
+	let mut bits = bytes_to_bits(entropy);
+	bits.extend(bytes_to_bits(&hash).into_iter().take(checksum_bits));
+
+	let list = wordlist();
+	let words = bits
+		.chunks(11)
+		.map(|chunk| {
+			let index = chunk.iter().fold(0usize, |acc, &bit| (acc << 1) | bit as usize);
+			list[index].as_str()
+		})
+		.collect::<Vec<_>>();
+	Ok(words.join(" "))
+}
+
+/// Decodes a mnemonic phrase produced by [`encode`] back into its original entropy bytes, validating that
+/// every word is in the wordlist and that the trailing checksum bits match.
+pub fn decode(phrase: &str) -> Result<Vec<u8>> {
+	let list = wordlist();
+	let words: Vec<&str> = phrase.split_whitespace().collect();
+	let entropy_bit_count = entropy_bits_for(words.len())?;
+
+	let mut bits = Vec::with_capacity(words.len() * 11);
+	for word in &words {
+		let index = list
+			.iter()
+			.position(|candidate| candidate == word)
+			.ok_or_else(|| anyhow!("{word:?} is not in the wordlist"))?;
+		bits.extend((0..11).rev().map(|i| (index >> i) & 1 == 1));
+	}
+
+	let entropy = bits_to_bytes(&bits[..entropy_bit_count]);
+	let claimed_checksum = &bits[entropy_bit_count..];
+	let hash = Sha256::digest(&entropy);
+	let actual_checksum = &bytes_to_bits(&hash)[..claimed_checksum.len()];
+	ensure!(claimed_checksum == actual_checksum, "mnemonic checksum mismatch");
+	Ok(entropy)
+}
+
+/// Like [`decode`], but if the phrase as given fails to decode, tries substituting every
+/// wordlist entry into each word position in turn and returns the entropy if exactly one such
+/// single-word correction produces a phrase that decodes successfully. Bails if no correction
+/// works, or if more than one does (the typo is ambiguous).
+pub fn decode_with_fuzzy_correction(phrase: &str) -> Result<Vec<u8>> {
+	let original_err = match decode(phrase) {
+		Ok(entropy) => return Ok(entropy),
+		Err(err) => err,
+	};
+
+	let words: Vec<&str> = phrase.split_whitespace().collect();
+	let list = wordlist();
+	let mut corrections = Vec::new();
+	for i in 0..words.len() {
+		for candidate in list.iter() {
+			if candidate == words[i] {
+				continue;
+			}
+			let mut attempt = words.clone();
+			attempt[i] = candidate.as_str();
+			if let Ok(entropy) = decode(&attempt.join(" ")) {
+				corrections.push(entropy);
+			}
+		}
+	}
+
+	match corrections.len() {
+		0 => Err(original_err).context("no single-word correction restores a valid checksum"),
+		1 => Ok(corrections.into_iter().next().expect("checked len == 1")),
+		_ => bail!("more than one single-word correction restores a valid checksum, can't disambiguate"),
+	}
+}
@@ -1,10 +1,17 @@
 use std::collections::BTreeSet;
 
-use anyhow::Result;
-use chrono::{DateTime, Utc};
+use anyhow::{Result, ensure};
+use chrono::{DateTime, Duration, Utc};
 use nix_eval::{Value, nix_go, nix_go_json};
 
-use crate::fleetdata::FleetSecretData;
+use crate::{fleetdata::FleetSecretData, template::SecretTemplate};
+
+/// Default `rotation_window` for secrets whose definition doesn't set one: proactively treat a
+/// secret as due for regeneration a day before it actually expires, rather than only noticing
+/// once it's already dead.
+pub fn default_rotation_window() -> Duration {
+	Duration::hours(24)
+}
 
 #[derive(Debug)]
 pub struct Expectations {
@@ -12,6 +19,20 @@ pub struct Expectations {
 	pub generation_data: serde_json::Value,
 	pub public_parts: BTreeSet<String>,
 	pub private_parts: BTreeSet<String>,
+	/// How many owners must together decrypt the secret to reconstruct it (Shamir's secret
+	/// sharing, see [`crate::shamir`]). `None`/`Some(1)` both mean "any single owner suffices",
+	/// matching the historical behavior; only shared secrets can set this above `1`.
+	pub threshold: Option<u32>,
+	/// Whether removing an owner should force full regeneration of the secret value (making the
+	/// removed owner's copy worthless) rather than just leaving their stanza in place. Always
+	/// `false` for host secrets, which have only one owner to begin with.
+	pub rotate_on_remove: bool,
+	/// How far ahead of `expires_at` [`secret_needs_regeneration`] starts reporting
+	/// [`RegenerationReason::ExpiringSoon`], so `secrets regenerate` can rotate a secret before it
+	/// actually goes dead rather than racing its expiration. Defaults to
+	/// [`default_rotation_window`]; a secret definition may override it via `rotationWindow`
+	/// (seconds).
+	pub rotation_window: Duration,
 }
 
 pub struct HostSecretDefinition(pub(crate) String, pub(crate) Value);
@@ -38,17 +59,34 @@ impl HostSecretDefinition {
 			}
 		}
 
+		let rotation_window_secs: Option<i64> = nix_go_json!(def.rotationWindow);
+		let rotation_window = rotation_window_secs
+			.map(Duration::seconds)
+			.unwrap_or_else(default_rotation_window);
+
 		Ok(Expectations {
 			owners: BTreeSet::from([self.0.clone()]),
 			generation_data: nix_go_json!(def.expectedGenerationData),
 			public_parts,
 			private_parts,
+			// Host secrets have exactly one owner, there's nothing to split a threshold across.
+			threshold: None,
+			// ...nor anything to rotate away from.
+			rotate_on_remove: false,
+			rotation_window,
 		})
 	}
 	pub fn definition_value(&self) -> Result<Value> {
 		let value = &self.1;
 		Ok(nix_go!(value.definition))
 	}
+	/// Which [`SecretTemplate`] `secrets edit` should render this secret as; `Untyped` (a single
+	/// opaque part) unless the definition sets a `template` attribute.
+	pub fn template(&self) -> Result<SecretTemplate> {
+		let def = self.definition_value()?;
+		let template: Option<String> = nix_go_json!(def.template);
+		SecretTemplate::parse(template.as_deref())
+	}
 }
 
 pub struct SharedSecretDefinition(pub(crate) Value);
@@ -59,16 +97,44 @@ impl SharedSecretDefinition {
 	}
 	pub fn expectations(&self) -> Result<Expectations> {
 		let value = &self.0;
+		let owners: BTreeSet<String> = nix_go_json!(value.expectedOwners);
+		let threshold: Option<u32> = nix_go_json!(value.expectedThreshold);
+		if let Some(threshold) = threshold {
+			ensure!(threshold >= 1, "expectedThreshold must be at least 1");
+			ensure!(
+				threshold as usize <= owners.len(),
+				"expectedThreshold ({threshold}) can't exceed the number of owners ({})",
+				owners.len()
+			);
+		}
+		let rotation_window_secs: Option<i64> = nix_go_json!(value.rotationWindow);
+		let rotation_window = rotation_window_secs
+			.map(Duration::seconds)
+			.unwrap_or_else(default_rotation_window);
+
 		Ok(Expectations {
-			owners: nix_go_json!(value.expectedOwners),
+			owners,
 			generation_data: nix_go_json!(value.expectedGenerationData),
 			public_parts: nix_go_json!(value.expectedPublicParts),
 			private_parts: nix_go_json!(value.expectedPrivateParts),
+			threshold,
+			// The definition's `regenerateOnOwnerRemoved` flag is the default policy for whether
+			// an owner *removal* warrants forcing full regeneration (revocation); owner additions
+			// are governed separately by `regenerateOnOwnerAdded`.
+			rotate_on_remove: nix_go_json!(value.regenerateOnOwnerRemoved),
+			rotation_window,
 		})
 	}
 	pub fn definition_value(&self) -> Value {
 		self.0.clone()
 	}
+	/// Which [`SecretTemplate`] `secrets edit` should render this secret as; `Untyped` (a single
+	/// opaque part) unless the definition sets a `template` attribute.
+	pub fn template(&self) -> Result<SecretTemplate> {
+		let value = &self.0;
+		let template: Option<String> = nix_go_json!(value.template);
+		SecretTemplate::parse(template.as_deref())
+	}
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -93,6 +159,19 @@ pub enum RegenerationReason {
 	ExpectedPublic(String),
 	#[error("secret is expired at {0}")]
 	Expired(DateTime<Utc>),
+	/// Distinct from [`Self::Expired`]: the secret is still valid, but `expires_at` falls inside
+	/// its `rotation_window`, so it's time to proactively rotate it rather than wait for it to
+	/// actually go dead.
+	#[error("secret expires at {expires_at} which is within the {within:?} rotation window")]
+	ExpiringSoon {
+		expires_at: DateTime<Utc>,
+		within: Duration,
+	},
+	#[error("threshold changed, expected: {expected:?}, found: {found:?}")]
+	ThresholdChanged {
+		expected: Option<u32>,
+		found: Option<u32>,
+	},
 }
 
 pub fn secret_needs_regeneration(
@@ -112,6 +191,16 @@ pub fn secret_needs_regeneration(
 		}
 	}
 
+	// `None` and `Some(1)` are equivalent ("any single owner decrypts it alone"), so don't treat
+	// that as a change worth a re-split.
+	let normalize_threshold = |t: Option<u32>| t.filter(|t| *t > 1);
+	if normalize_threshold(secret.threshold) != normalize_threshold(expectations.threshold) {
+		return Some(RegenerationReason::ThresholdChanged {
+			expected: expectations.threshold,
+			found: secret.threshold,
+		});
+	}
+
 	if secret.generation_data != expectations.generation_data {
 		return Some(RegenerationReason::GenerationData {
 			expected: expectations.generation_data.clone(),
@@ -120,33 +209,49 @@ pub fn secret_needs_regeneration(
 	}
 
 	if !expectations.public_parts.is_empty() || !expectations.private_parts.is_empty() {
+		// Threshold shared secrets store each owner's Shamir share under `"{part}@{owner}"` (see
+		// `share_key` in `cmds/fleet/src/cmds/secrets/mod.rs`), while `expectations` only knows
+		// about the bare part names from the Nix definition - strip the suffix before comparing,
+		// same as `resplit_shared_secret` does.
+		let base_name = |key: &str| -> String {
+			key.split_once('@')
+				.map_or_else(|| key.to_owned(), |(base, _)| base.to_owned())
+		};
+
 		let expected: BTreeSet<String> = expectations
 			.public_parts
 			.union(&expectations.private_parts)
 			.cloned()
 			.collect();
-		let found: BTreeSet<String> = secret.parts.keys().cloned().collect();
+		let found: BTreeSet<String> = secret.parts.keys().map(|key| base_name(key)).collect();
 
 		if found != expected {
 			return Some(RegenerationReason::PartList { expected, found });
 		}
 
 		for (name, value) in secret.parts.iter() {
+			let name = base_name(name);
 			if value.raw.encrypted {
-				if !expectations.private_parts.contains(name) {
-					return Some(RegenerationReason::ExpectedPrivate(name.clone()));
+				if !expectations.private_parts.contains(&name) {
+					return Some(RegenerationReason::ExpectedPrivate(name));
 				}
-			} else if !expectations.public_parts.contains(name) {
-				return Some(RegenerationReason::ExpectedPublic(name.clone()));
+			} else if !expectations.public_parts.contains(&name) {
+				return Some(RegenerationReason::ExpectedPublic(name));
 			}
 		}
 	}
 
 	if let Some(expiration) = secret.expires_at {
-		// TODO: Leeway?
-		if expiration < Utc::now() {
+		let now = Utc::now();
+		if expiration < now {
 			return Some(RegenerationReason::Expired(expiration));
 		}
+		if expiration < now + expectations.rotation_window {
+			return Some(RegenerationReason::ExpiringSoon {
+				expires_at: expiration,
+				within: expectations.rotation_window,
+			});
+		}
 	}
 
 	None
@@ -0,0 +1,177 @@
+//! Shamir's Secret Sharing over GF(256), used by threshold shared secrets (a secret split
+//! across N owners such that any K of them, but no fewer, can reconstruct it).
+//!
+//! Each byte of the secret is the constant term of an independent random degree-(K-1)
+//! polynomial, evaluated at the distinct nonzero points `x = 1..=N` to produce the N shares.
+//! Reconstruction interpolates each byte's polynomial at `x = 0` from any K of those shares.
+//! Field arithmetic is standard AES GF(256) (reduction polynomial `0x11B`): addition is XOR,
+//! multiplication goes through log/exp tables built from the generator `0x03`.
+
+use anyhow::{Result, bail, ensure};
+use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+
+const GF_EXP_SIZE: usize = 512;
+
+struct GfTables {
+	exp: [u8; GF_EXP_SIZE],
+	log: [u8; 256],
+}
+
+fn gf_tables() -> &'static GfTables {
+	static TABLES: std::sync::OnceLock<GfTables> = std::sync::OnceLock::new();
+	TABLES.get_or_init(|| {
+		let mut exp = [0u8; GF_EXP_SIZE];
+		let mut log = [0u8; 256];
+		let mut x: u8 = 1;
+		for i in 0..255 {
+			exp[i] = x;
+			log[x as usize] = i as u8;
+			// Multiply x by the generator 3: x*3 = x*2 ^ x, with x*2 reduced mod 0x11B.
+			let doubled = if x & 0x80 != 0 { (x << 1) ^ 0x1B } else { x << 1 };
+			x = doubled ^ x;
+		}
+		for i in 255..GF_EXP_SIZE {
+			exp[i] = exp[i - 255];
+		}
+		GfTables { exp, log }
+	})
+}
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+	if a == 0 || b == 0 {
+		return 0;
+	}
+	let tables = gf_tables();
+	let log_sum = tables.log[a as usize] as usize + tables.log[b as usize] as usize;
+	tables.exp[log_sum]
+}
+
+fn gf_div(a: u8, b: u8) -> u8 {
+	assert!(b != 0, "division by zero share point");
+	if a == 0 {
+		return 0;
+	}
+	let tables = gf_tables();
+	let log_diff = tables.log[a as usize] as isize - tables.log[b as usize] as isize;
+	tables.exp[log_diff.rem_euclid(255) as usize]
+}
+
+fn gf_pow(a: u8, mut e: u32) -> u8 {
+	let mut result = 1u8;
+	let mut base = a;
+	while e > 0 {
+		if e & 1 == 1 {
+			result = gf_mul(result, base);
+		}
+		base = gf_mul(base, base);
+		e >>= 1;
+	}
+	result
+}
+
+/// Evaluates the degree-(`coefficients.len() - 1`) polynomial with the given coefficients
+/// (lowest degree first) at `x`, in GF(256).
+fn eval_polynomial(coefficients: &[u8], x: u8) -> u8 {
+	coefficients
+		.iter()
+		.enumerate()
+		.fold(0u8, |acc, (degree, &c)| acc ^ gf_mul(c, gf_pow(x, degree as u32)))
+}
+
+/// Splits `secret` into `shares` shares such that any `threshold` of them reconstruct it, but
+/// any `threshold - 1` reveal nothing. Byte-wise: each byte becomes the constant term of a
+/// random degree-(`threshold - 1`) polynomial, evaluated at `x = 1..=shares`.
+///
+/// Returns one share per owner, in order, as `(x, bytes)` pairs; `x` is never `0` (that point
+/// is the secret itself) and is stable across calls only within a single split — a re-split
+/// (e.g. after the owner set changes) assigns fresh random polynomials and thus fresh shares.
+pub fn split(secret: &[u8], threshold: u32, shares: u32) -> Result<Vec<(u8, Vec<u8>)>> {
+	ensure!(threshold >= 1, "threshold must be at least 1");
+	ensure!(threshold <= shares, "threshold ({threshold}) must not exceed the number of shares ({shares})");
+	ensure!(shares <= 255, "can't split a secret into more than 255 shares (GF(256) has only 255 nonzero points)");
+
+	let mut out: Vec<(u8, Vec<u8>)> = (1..=shares).map(|x| (x as u8, Vec::with_capacity(secret.len()))).collect();
+	let mut coefficients = vec![0u8; threshold as usize];
+	for &byte in secret {
+		coefficients[0] = byte;
+		if threshold > 1 {
+			let mut random = vec![0u8; threshold as usize - 1];
+			OsRng.fill_bytes(&mut random);
+			coefficients[1..].copy_from_slice(&random);
+		}
+		for (x, share) in out.iter_mut() {
+			share.push(eval_polynomial(&coefficients, *x));
+		}
+	}
+	Ok(out)
+}
+
+/// Reconstructs the original secret from `shares` (each an `(x, bytes)` pair as produced by
+/// [`split`]) via Lagrange interpolation at `x = 0`, byte by byte. Any `threshold` correct
+/// shares reconstruct the secret regardless of which ones are provided; providing fewer than
+/// `threshold` silently returns a wrong result, since GF(256) interpolation can't detect that
+/// on its own, so callers must themselves enforce that at least `threshold` shares are passed.
+pub fn reconstruct(shares: &[(u8, Vec<u8>)]) -> Result<Vec<u8>> {
+	let Some(len) = shares.first().map(|(_, s)| s.len()) else {
+		bail!("no shares provided");
+	};
+	ensure!(
+		shares.iter().all(|(_, s)| s.len() == len),
+		"shares have mismatched lengths"
+	);
+	ensure!(
+		shares.iter().all(|(x, _)| *x != 0),
+		"share point can't be 0, that point is reserved for the secret itself"
+	);
+
+	let mut secret = vec![0u8; len];
+	for byte_idx in 0..len {
+		let mut acc = 0u8;
+		for (i, (xi, si)) in shares.iter().enumerate() {
+			let mut numerator = 1u8;
+			let mut denominator = 1u8;
+			for (j, (xj, _)) in shares.iter().enumerate() {
+				if i == j {
+					continue;
+				}
+				numerator = gf_mul(numerator, *xj);
+				denominator = gf_mul(denominator, *xi ^ *xj);
+			}
+			let lagrange_coefficient = gf_div(numerator, denominator);
+			acc ^= gf_mul(si[byte_idx], lagrange_coefficient);
+		}
+		secret[byte_idx] = acc;
+	}
+	Ok(secret)
+}
+
+#[test]
+fn test_generator_has_full_order() {
+	// The GF(256) log/exp tables only cover every nonzero element if the generator used to
+	// build them actually has order 255; this would silently regress to a generator like `2`
+	// (order 51) producing mostly-zero log entries.
+	let tables = gf_tables();
+	let mut seen = std::collections::HashSet::new();
+	for &value in &tables.exp[..255] {
+		assert!(seen.insert(value), "generator repeated {value} before covering all 255 nonzero elements");
+	}
+	assert_eq!(seen.len(), 255);
+}
+
+#[test]
+fn test_split_reconstruct_roundtrip() {
+	for threshold in 1u32..=5 {
+		for shares in threshold..=8 {
+			for secret in [b"".as_slice(), b"a", b"hello world, 16!", b"0123456789abcdef0123456789abcdef"] {
+				let split_shares = split(secret, threshold, shares).expect("split failed");
+				// Any `threshold`-sized subset must reconstruct the original secret.
+				let subset = &split_shares[..threshold as usize];
+				let reconstructed = reconstruct(subset).expect("reconstruct failed");
+				assert_eq!(
+					reconstructed, secret,
+					"roundtrip mismatch for threshold={threshold}, shares={shares}, secret={secret:?}"
+				);
+			}
+		}
+	}
+}
@@ -0,0 +1,220 @@
+//! Content-defined-chunking deduplication layered on top of closure export, so re-deploying
+//! a closure that's only incrementally different from what a host already has moves just
+//! the novel bytes instead of resending the whole NAR stream every time.
+//!
+//! Each store path's NAR is split into variable-length chunks with a rolling buzhash, so
+//! insertions/deletions in the underlying files shift chunk boundaries only locally instead
+//! of invalidating every chunk after the edit (unlike fixed-size blocking). Chunks are named
+//! by their BLAKE2b digest; a chunk is only ever sent to a remote once it tells us it
+//! doesn't already have that digest.
+
+use std::{
+	collections::{HashMap, HashSet},
+	io::Read,
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result, bail};
+use blake2::{Blake2b, Digest as _, digest::consts::U32};
+
+/// Rolling-hash window, in bytes. Wide enough that the hash reflects a meaningful span of
+/// content, narrow enough to stay cheap to roll a byte at a time.
+const WINDOW: usize = 48;
+const MIN_CHUNK: usize = 16 * 1024;
+const AVG_CHUNK: usize = 64 * 1024;
+const MAX_CHUNK: usize = 256 * 1024;
+/// Cut whenever the rolling hash's low bits are all zero, which happens on average once
+/// every `AVG_CHUNK` bytes since `AVG_CHUNK` is a power of two.
+const CUT_MASK: u64 = AVG_CHUNK as u64 - 1;
+
+pub type ChunkDigest = [u8; 32];
+type Blake2b256 = Blake2b<U32>;
+
+fn digest(data: &[u8]) -> ChunkDigest {
+	Blake2b256::digest(data).into()
+}
+
+const fn splitmix64(seed: u64) -> u64 {
+	let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+	z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+	z ^ (z >> 31)
+}
+
+/// Per-byte-value table for the buzhash, filled deterministically at compile time (not
+/// randomly per-run) - both sides of a transfer must derive identical chunk boundaries from
+/// identical input for digests to line up.
+const fn buzhash_table() -> [u64; 256] {
+	let mut table = [0u64; 256];
+	let mut i = 0;
+	while i < 256 {
+		table[i] = splitmix64(i as u64 + 1);
+		i += 1;
+	}
+	table
+}
+static BUZHASH_TABLE: [u64; 256] = buzhash_table();
+
+/// A cyclic-polynomial (buzhash) rolling hash over the trailing `WINDOW` bytes seen so far.
+struct Buzhash {
+	window: std::collections::VecDeque<u8>,
+	hash: u64,
+}
+impl Buzhash {
+	fn new() -> Self {
+		Self {
+			window: std::collections::VecDeque::with_capacity(WINDOW),
+			hash: 0,
+		}
+	}
+	fn push(&mut self, byte: u8) -> u64 {
+		self.hash = self.hash.rotate_left(1) ^ BUZHASH_TABLE[byte as usize];
+		self.window.push_back(byte);
+		if self.window.len() > WINDOW {
+			let out = self.window.pop_front().expect("window just overflowed");
+			let rot = (WINDOW as u32) % 64;
+			self.hash ^= BUZHASH_TABLE[out as usize].rotate_left(rot);
+		}
+		self.hash
+	}
+}
+
+/// Splits `r` into content-defined chunks, calling `on_chunk` with each one in turn.
+/// Chunks are at least `MIN_CHUNK` bytes (except possibly the very last one) and at most
+/// `MAX_CHUNK`, averaging `AVG_CHUNK`.
+fn chunk_stream(r: impl Read, mut on_chunk: impl FnMut(&[u8]) -> Result<()>) -> Result<()> {
+	let mut r = std::io::BufReader::new(r);
+	let mut buzhash = Buzhash::new();
+	let mut chunk = Vec::with_capacity(AVG_CHUNK);
+	let mut byte = [0u8; 1];
+	loop {
+		if r.read(&mut byte)? == 0 {
+			break;
+		}
+		chunk.push(byte[0]);
+		let hash = buzhash.push(byte[0]);
+		let should_cut =
+			chunk.len() >= MAX_CHUNK || (chunk.len() >= MIN_CHUNK && hash & CUT_MASK == 0);
+		if should_cut {
+			on_chunk(&chunk)?;
+			chunk.clear();
+			buzhash = Buzhash::new();
+		}
+	}
+	if !chunk.is_empty() {
+		on_chunk(&chunk)?;
+	}
+	Ok(())
+}
+
+fn hex_encode(digest: &ChunkDigest) -> String {
+	digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A local content-addressed cache of chunks, keyed by BLAKE2b digest, under `dir`. Reused
+/// across deploys so that re-sending a closure only has to re-chunk it, not re-fetch chunks
+/// it already produced before.
+pub struct ChunkIndex {
+	dir: PathBuf,
+}
+impl ChunkIndex {
+	pub fn new(dir: PathBuf) -> Result<Self> {
+		std::fs::create_dir_all(&dir).with_context(|| format!("creating chunk index at {dir:?}"))?;
+		Ok(Self { dir })
+	}
+	fn path_for(&self, digest: &ChunkDigest) -> PathBuf {
+		let hex = hex_encode(digest);
+		self.dir.join(&hex[0..2]).join(&hex[2..])
+	}
+	pub fn has(&self, digest: &ChunkDigest) -> bool {
+		self.path_for(digest).is_file()
+	}
+	pub fn put(&self, digest: &ChunkDigest, data: &[u8]) -> Result<()> {
+		if self.has(digest) {
+			return Ok(());
+		}
+		let path = self.path_for(digest);
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+		std::fs::write(path, data).with_context(|| format!("writing chunk to {:?}", self.path_for(digest)))
+	}
+}
+
+/// The remote side of a sync: told which digests we're about to send and asked which ones it
+/// doesn't already have, then handed the bodies of exactly those.
+pub trait RemoteChunkStore {
+	fn missing(&mut self, digests: &[ChunkDigest]) -> Result<HashSet<ChunkDigest>>;
+	fn put(&mut self, digest: ChunkDigest, data: &[u8]) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncStats {
+	pub total_bytes: u64,
+	pub transferred_bytes: u64,
+}
+impl SyncStats {
+	/// Fraction of bytes that dedup avoided sending, in `[0, 1]`.
+	pub fn dedup_ratio(&self) -> f64 {
+		if self.total_bytes == 0 {
+			return 0.0;
+		}
+		1.0 - (self.transferred_bytes as f64 / self.total_bytes as f64)
+	}
+}
+
+/// Chunks the full closure of `path` (`nix-store --query --requisites`, each dumped via
+/// `nix-store --dump`), keeps every chunk in `index`, and sends only the chunk bodies
+/// `remote` reports missing.
+pub fn sync_closure(
+	path: &Path,
+	index: &ChunkIndex,
+	remote: &mut impl RemoteChunkStore,
+) -> Result<SyncStats> {
+	let requisites = Command::new("nix-store")
+		.arg("--query")
+		.arg("--requisites")
+		.arg(path)
+		.output()
+		.context("listing closure with nix-store --query --requisites")?;
+	if !requisites.status.success() {
+		bail!("nix-store --query --requisites failed: {}", requisites.status);
+	}
+	let requisites = String::from_utf8(requisites.stdout).context("nix-store output is not utf-8")?;
+
+	let mut stats = SyncStats::default();
+	let mut chunks: HashMap<ChunkDigest, Vec<u8>> = HashMap::new();
+	for store_path in requisites.lines() {
+		let mut dump = Command::new("nix-store")
+			.arg("--dump")
+			.arg(store_path)
+			.stdout(Stdio::piped())
+			.spawn()
+			.context("spawning nix-store --dump")?;
+		let stdout = dump.stdout.take().expect("stdout was piped");
+		chunk_stream(stdout, |chunk| {
+			let digest = digest(chunk);
+			stats.total_bytes += chunk.len() as u64;
+			index.put(&digest, chunk)?;
+			chunks.entry(digest).or_insert_with(|| chunk.to_vec());
+			Ok(())
+		})?;
+		let status = dump.wait().context("waiting for nix-store --dump")?;
+		if !status.success() {
+			bail!("nix-store --dump {store_path} failed: {status}");
+		}
+	}
+
+	let digests: Vec<ChunkDigest> = chunks.keys().copied().collect();
+	let missing = remote.missing(&digests)?;
+	for digest in missing {
+		let Some(data) = chunks.get(&digest) else {
+			continue;
+		};
+		remote.put(digest, data)?;
+		stats.transferred_bytes += data.len() as u64;
+	}
+
+	Ok(stats)
+}
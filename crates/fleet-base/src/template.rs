@@ -0,0 +1,142 @@
+//! Typed secret "templates" (Bitwarden-style entry types: Login, SecureNote, Card, Identity), so
+//! `secrets edit` can render/parse a structured multi-field document instead of editing a single
+//! opaque blob. A secret's definition opts into one by name via its `template` attribute; the
+//! default, [`SecretTemplate::Untyped`], keeps the historical single-part editing behavior.
+
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{Result, bail, ensure};
+
+/// One field of a [`SecretTemplate`]: its name (also the `parts` key it's stored under) and
+/// whether `secrets edit` should refuse to save without it.
+#[derive(Debug, Clone, Copy)]
+pub struct TemplateField {
+	pub name: &'static str,
+	pub required: bool,
+}
+
+const fn field(name: &'static str, required: bool) -> TemplateField {
+	TemplateField { name, required }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretTemplate {
+	/// A single opaque part (the historical behavior), edited directly rather than as labeled
+	/// fields.
+	Untyped,
+	Login,
+	SecureNote,
+	Card,
+	Identity,
+}
+
+impl SecretTemplate {
+	/// Parses a definition's `template` attribute (a plain string), defaulting to `Untyped` when
+	/// unset.
+	pub fn parse(name: Option<&str>) -> Result<Self> {
+		Ok(match name.unwrap_or("untyped") {
+			"untyped" => Self::Untyped,
+			"login" => Self::Login,
+			"secureNote" => Self::SecureNote,
+			"card" => Self::Card,
+			"identity" => Self::Identity,
+			other => bail!("unknown secret template {other:?}"),
+		})
+	}
+
+	pub fn fields(self) -> &'static [TemplateField] {
+		match self {
+			Self::Untyped => &[],
+			Self::Login => &[
+				field("username", false),
+				field("password", true),
+				field("totp", false),
+				field("url", false),
+			],
+			Self::SecureNote => &[field("note", true)],
+			Self::Card => &[
+				field("cardholder", true),
+				field("number", true),
+				field("expiry", true),
+				field("cvv", true),
+			],
+			Self::Identity => &[
+				field("fullName", true),
+				field("email", false),
+				field("phone", false),
+				field("address", false),
+			],
+		}
+	}
+}
+
+impl std::fmt::Display for SecretTemplate {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Untyped => "untyped",
+			Self::Login => "login",
+			Self::SecureNote => "secure note",
+			Self::Card => "card",
+			Self::Identity => "identity",
+		})
+	}
+}
+
+/// Renders `values`' current contents (only the fields `template` defines) as a labeled buffer
+/// for `secrets edit`: each field as a `name:` line followed by its value and a blank separator,
+/// in the template's field order.
+pub fn render(template: SecretTemplate, values: &BTreeMap<String, Vec<u8>>) -> String {
+	use std::fmt::Write;
+
+	let mut out = String::new();
+	for f in template.fields() {
+		let value = values
+			.get(f.name)
+			.map(|v| String::from_utf8_lossy(v).into_owned())
+			.unwrap_or_default();
+		let _ = writeln!(out, "{}:", f.name);
+		let _ = writeln!(out, "{value}");
+		let _ = writeln!(out);
+	}
+	out
+}
+
+/// Parses a buffer produced (and possibly edited) by [`render`] back into field values, keyed by
+/// field name. A line that's exactly one of `template`'s field names followed by `:` starts a
+/// new field's value; everything up to the next such line (or end of buffer) is that field's
+/// value. Bails if a required field ends up missing or empty.
+pub fn parse(template: SecretTemplate, buffer: &str) -> Result<BTreeMap<String, Vec<u8>>> {
+	let field_names: HashSet<&str> = template.fields().iter().map(|f| f.name).collect();
+
+	let mut out = BTreeMap::new();
+	let mut current: Option<&str> = None;
+	let mut value_lines: Vec<&str> = Vec::new();
+
+	fn flush(out: &mut BTreeMap<String, Vec<u8>>, current: Option<&str>, value_lines: &mut Vec<&str>) {
+		if let Some(name) = current {
+			out.insert(name.to_owned(), value_lines.join("\n").trim_end().as_bytes().to_vec());
+		}
+		value_lines.clear();
+	}
+
+	for line in buffer.lines() {
+		if let Some(name) = line.strip_suffix(':').filter(|n| field_names.contains(n)) {
+			flush(&mut out, current, &mut value_lines);
+			current = Some(name);
+			continue;
+		}
+		value_lines.push(line);
+	}
+	flush(&mut out, current, &mut value_lines);
+
+	for f in template.fields() {
+		if f.required {
+			ensure!(
+				out.get(f.name).is_some_and(|v| !v.is_empty()),
+				"field {:?} is required but missing or empty",
+				f.name
+			);
+		}
+	}
+	Ok(out)
+}
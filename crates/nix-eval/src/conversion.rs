@@ -0,0 +1,151 @@
+//! Data-driven coercion of a Nix [`Value`] (typically a string-valued flake output or
+//! setting) into a concrete Rust type chosen at runtime by name, e.g. from a CLI flag or a
+//! config file, without the caller having to hand-write a match on [`NixType`].
+
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+use crate::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conversion {
+	Bytes,
+	String,
+	Integer,
+	Float,
+	Boolean,
+	Timestamp,
+	/// `Timestamp`, but parsed with an explicit chrono strftime pattern instead of guessing.
+	TimestampFormat(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedValue {
+	Bytes(Vec<u8>),
+	String(String),
+	Integer(i64),
+	Float(f64),
+	Boolean(bool),
+	Timestamp(DateTime<Utc>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConversionError {
+	#[error("unknown conversion: {name}")]
+	UnknownConversion { name: String },
+	#[error("failed to read value: {0}")]
+	Value(#[from] anyhow::Error),
+	#[error("failed to parse {value:?} as an integer: {source}")]
+	Integer {
+		value: String,
+		source: std::num::ParseIntError,
+	},
+	#[error("failed to parse {value:?} as a float: {source}")]
+	Float {
+		value: String,
+		source: std::num::ParseFloatError,
+	},
+	#[error("failed to parse {value:?} as a boolean")]
+	Boolean { value: String },
+	#[error("failed to parse {value:?} as a timestamp")]
+	Timestamp { value: String },
+}
+
+/// Common timestamp formats accepted by a bare `Conversion::Timestamp`, tried in order after
+/// RFC3339 fails. Intentionally small: anything more exotic should use the `timestamp|<fmt>`
+/// form instead of growing this list.
+const TIMESTAMP_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d"];
+
+impl FromStr for Conversion {
+	type Err = ConversionError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s.split_once('|') {
+			Some(("timestamp", format)) => Self::TimestampFormat(format.to_owned()),
+			_ => match s {
+				"bytes" => Self::Bytes,
+				"string" => Self::String,
+				"int" | "integer" => Self::Integer,
+				"float" => Self::Float,
+				"bool" | "boolean" => Self::Boolean,
+				"timestamp" => Self::Timestamp,
+				name => {
+					return Err(ConversionError::UnknownConversion {
+						name: name.to_owned(),
+					});
+				}
+			},
+		})
+	}
+}
+
+impl Conversion {
+	pub fn convert(&self, v: &Value) -> Result<TypedValue, ConversionError> {
+		let s = v.to_string()?;
+		Ok(match self {
+			Self::Bytes => TypedValue::Bytes(s.into_bytes()),
+			Self::String => TypedValue::String(s),
+			Self::Integer => {
+				let trimmed = s.trim();
+				let value = i64::from_str(trimmed).map_err(|source| ConversionError::Integer {
+					value: s.clone(),
+					source,
+				})?;
+				TypedValue::Integer(value)
+			}
+			Self::Float => {
+				let trimmed = s.trim();
+				let value = f64::from_str(trimmed).map_err(|source| ConversionError::Float {
+					value: s.clone(),
+					source,
+				})?;
+				TypedValue::Float(value)
+			}
+			Self::Boolean => {
+				let value = match s.trim() {
+					"true" | "1" => true,
+					"false" | "0" => false,
+					_ => return Err(ConversionError::Boolean { value: s }),
+				};
+				TypedValue::Boolean(value)
+			}
+			Self::Timestamp => TypedValue::Timestamp(parse_timestamp(&s)?),
+			Self::TimestampFormat(format) => {
+				TypedValue::Timestamp(parse_timestamp_format(&s, format)?)
+			}
+		})
+	}
+}
+
+fn parse_timestamp(s: &str) -> Result<DateTime<Utc>, ConversionError> {
+	if let Ok(dt) = DateTime::parse_from_rfc3339(s.trim()) {
+		return Ok(dt.with_timezone(&Utc));
+	}
+	for format in TIMESTAMP_FORMATS {
+		if let Ok(dt) = parse_timestamp_format(s, format) {
+			return Ok(dt);
+		}
+	}
+	Err(ConversionError::Timestamp {
+		value: s.to_owned(),
+	})
+}
+
+fn parse_timestamp_format(s: &str, format: &str) -> Result<DateTime<Utc>, ConversionError> {
+	let trimmed = s.trim();
+	if let Ok(dt) = DateTime::parse_from_str(trimmed, format) {
+		return Ok(dt.with_timezone(&Utc));
+	}
+	let naive = match NaiveDateTime::parse_from_str(trimmed, format) {
+		Ok(naive) => naive,
+		// A date-only format has no time fields, so read it as midnight.
+		Err(_) => NaiveDate::parse_from_str(trimmed, format)
+			.ok()
+			.and_then(|date| date.and_hms_opt(0, 0, 0))
+			.ok_or_else(|| ConversionError::Timestamp {
+				value: s.to_owned(),
+			})?,
+	};
+	Ok(naive.and_utc())
+}
@@ -29,8 +29,9 @@ use self::nix_raw::{
 	flake_reference_parse_flags_free, flake_reference_parse_flags_new,
 	flake_reference_parse_flags_set_base_directory, flake_settings, flake_settings_free,
 	flake_settings_new, gc_now as gc_now_raw, get_attr_byname, get_attr_name_byidx, get_attrs_size,
-	get_list_byidx, get_list_size, get_string, get_type, has_attr_byname, init_bool, init_int,
-	init_string, libexpr_init, libstore_init, libutil_init, list_builder_free, list_builder_insert,
+	get_bool, get_float, get_int, get_list_byidx, get_list_size, get_string, get_type,
+	has_attr_byname, init_bool, init_int, init_string, libexpr_init, libstore_init, libutil_init,
+	list_builder_free, list_builder_insert,
 	locked_flake, locked_flake_free, locked_flake_get_output_attrs, make_attrs,
 	make_bindings_builder, make_list, make_list_builder, realised_string, realised_string_free,
 	realised_string_get_buffer_size, realised_string_get_buffer_start,
@@ -39,11 +40,21 @@ use self::nix_raw::{
 	value, value_call, value_decref, value_incref,
 };
 
+pub mod conversion;
 // Contains macros helpers
 pub mod logging;
 #[doc(hidden)]
 pub mod macros;
+pub mod netencode;
 pub mod util;
+mod value_de;
+
+// `nix_expr_inner!` is a real recursive-descent proc-macro (see the `nix-expr-macros`
+// crate) rather than the old single-level `macro_rules!` token-muncher, so that
+// `Obj { .. }` field values and list items can nest arbitrarily deep. It's re-exported
+// at the crate root so `nix_expr!`/`nix_go!` can keep invoking it as `$crate::nix_expr_inner!`.
+#[doc(hidden)]
+pub use nix_expr_macros::nix_expr_inner;
 
 #[allow(
 	non_upper_case_globals,
@@ -240,6 +251,8 @@ struct GlobalState {
 }
 impl GlobalState {
 	fn new() -> Result<Self> {
+		assert_gc_alloc_active();
+
 		let mut ctx = NixContext::new();
 		let store = ctx
 			.run_in_context(|c| unsafe { store_open(c, c"auto".as_ptr(), null_mut()) })
@@ -456,6 +469,8 @@ impl FlakeReference {
 		flake: &FlakeSettings,
 		lock: &FlakeLockFlags,
 	) -> Result<LockedFlake> {
+		#[cfg(feature = "boehm-gc-alloc")]
+		let _gc_guard = GcDisabledGuard::new();
 		with_default_context(|c, es| unsafe { flake_lock(c, fetch.0, flake.0, es, lock.0, self.0) })
 			.map(LockedFlake)
 	}
@@ -529,6 +544,10 @@ impl Drop for RealisedString {
 
 pub struct Value(*mut value);
 
+// Sound only on threads registered with the Boehm collector (see `gc_register_my_thread`/
+// `ThreadRegisterGuard`), since the GC needs to be able to find the values' roots on every
+// thread that might hold a reference. Don't move a `Value` onto a thread by hand; go through
+// an entry point like `Value::par_map_fields` that registers the thread for you.
 unsafe impl Send for Value {}
 unsafe impl Sync for Value {}
 
@@ -682,6 +701,15 @@ impl Value {
 
 		Ok(str_out)
 	}
+	pub fn as_int(&self) -> Result<i64> {
+		with_default_context(|c, _| unsafe { get_int(c, self.0) })
+	}
+	pub fn as_bool(&self) -> Result<bool> {
+		with_default_context(|c, _| unsafe { get_bool(c, self.0) })
+	}
+	pub fn as_float(&self) -> Result<f64> {
+		with_default_context(|c, _| unsafe { get_float(c, self.0) })
+	}
 	pub fn to_realised_string(&self) -> Result<RealisedString> {
 		with_default_context(|c, es| unsafe { string_realise(c, es, self.0, false) })
 			.map(RealisedString)
@@ -718,11 +746,14 @@ impl Value {
 		}
 		Ok(out)
 	}
-	pub fn get_elem(&self, v: usize) -> Result<Self> {
+	pub fn list_size(&self) -> Result<usize> {
 		if !matches!(self.type_of(), NixType::List) {
 			bail!("invalid type: expected list");
 		}
-		let len = with_default_context(|c, _| unsafe { get_list_size(c, self.0) })? as usize;
+		Ok(with_default_context(|c, _| unsafe { get_list_size(c, self.0) })? as usize)
+	}
+	pub fn get_elem(&self, v: usize) -> Result<Self> {
+		let len = self.list_size()?;
 		if v >= len {
 			bail!("oob list get: {v} >= {len}");
 		}
@@ -773,6 +804,8 @@ impl Value {
 		Ok(out)
 	}
 	pub fn eval(v: &str) -> Result<Self> {
+		#[cfg(feature = "boehm-gc-alloc")]
+		let _gc_guard = GcDisabledGuard::new();
 		let s = CString::new(v).expect("expression shouldn't have internal NULs");
 		let out = Self::new_uninit();
 		with_default_context(|c, es| unsafe {
@@ -808,9 +841,24 @@ impl Value {
 		let s = to_json.call(self.clone())?.to_string()?;
 		Ok(serde_json::from_str(&s)?)
 	}
+	/// Like [`Self::as_json`], but walks the value structurally instead of round-tripping
+	/// through `builtins.toJSON` + `serde_json`. Avoids forcing a full string render of the
+	/// value just to read a handful of fields out of a large attrset.
+	pub fn as_native<T: DeserializeOwned>(&self) -> Result<T> {
+		T::deserialize(value_de::ValueDeserializer(self)).map_err(|e| anyhow!("{e}"))
+	}
 	pub fn serialized<T: Serialize>(v: &T) -> Result<Self> {
 		Self::eval(&nixlike::serialize(v)?)
 	}
+	/// Like [`Self::serialized`], but for data that may not be valid UTF-8 (derivation
+	/// ATerm env values, raw subprocess output, ...) and so can't go through
+	/// `serde_json`'s `Value::String`. Renders `v` as a byte-safe Nix string literal
+	/// directly, instead of through [`nixlike::serialize`]'s `Serialize`-based path.
+	/// Errors out if `v` contains a NUL byte or a byte `0x80..=0xff`, neither of which can
+	/// be represented this way without lossy re-encoding.
+	pub fn serialized_bytes(v: &[u8]) -> Result<Self> {
+		Self::eval(&nixlike::write_nix(&nixlike::Value::Bytes(v.to_vec()))?)
+	}
 
 	// Convert to string/evaluate derivations/etc
 	// fn to_string_weak(&self) -> Result<String> {
@@ -938,21 +986,125 @@ fn test_native() -> Result<()> {
 	Ok(())
 }
 
-// pub struct GcAlloc;
-// unsafe impl GlobalAlloc for GcAlloc {
-// 	unsafe fn alloc(&self, l: Layout) -> *mut u8 {
-// 		let ptr = unsafe { GC_malloc(l.size()) };
-// 		ptr.cast()
-// 	}
-// 	unsafe fn dealloc(&self, ptr: *mut u8, _: Layout) {
-// 		// unsafe { GC_free(ptr.cast()) };
-// 	}
-//
-// 	unsafe fn realloc(&self, ptr: *mut u8, _: Layout, new_size: usize) -> *mut u8 {
-// 		let ptr = unsafe { GC_realloc(ptr.cast(), new_size) };
-// 		ptr.cast()
-// 	}
-// }
-//
-// #[global_allocator]
-// static GC: GcAlloc = GcAlloc;
+/// Alignment below which `GC_malloc` is already good enough, same threshold `std`'s own
+/// `System` allocator uses on targets (WASI, some Unixes) where malloc only guarantees this
+/// much: above it we have to ask for the alignment explicitly via `GC_memalign`.
+#[cfg(feature = "boehm-gc-alloc")]
+const GC_MIN_ALIGN: usize = if cfg!(target_pointer_width = "64") {
+	16
+} else {
+	8
+};
+
+/// Makes sure `GC_init`/`GC_allow_register_threads` have run before the very first
+/// allocation. This can't piggyback on [`init_libraries`] because the global allocator may
+/// see its first `alloc` call before `main` (e.g. from another crate's statics), so it's
+/// guarded by its own, separate `OnceLock`.
+#[cfg(feature = "boehm-gc-alloc")]
+fn ensure_gc_alloc_ready() {
+	static INIT: std::sync::OnceLock<()> = std::sync::OnceLock::new();
+	INIT.get_or_init(|| unsafe {
+		nix_raw::GC_init();
+		GC_allow_register_threads();
+	});
+}
+
+/// A `GlobalAlloc` backed by the Boehm collector, so that Rust-side allocations holding
+/// `Value`s (or anything reachable from one) live on the same GC-scanned heap as `libexpr`'s
+/// own allocations, instead of a heap the collector never looks at. Enable with the
+/// `boehm-gc-alloc` feature; see the module-level correctness note on [`Value`]'s `Send`/
+/// `Sync` impls for why this matters.
+#[cfg(feature = "boehm-gc-alloc")]
+pub struct GcAlloc;
+
+#[cfg(feature = "boehm-gc-alloc")]
+unsafe impl std::alloc::GlobalAlloc for GcAlloc {
+	unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+		ensure_gc_alloc_ready();
+		// `GC_malloc` zeroes its result, and per the Boehm docs a zero-size request still
+		// returns a unique, non-null pointer (distinct from every other live allocation),
+		// matching what `GlobalAlloc` callers are allowed to assume.
+		let ptr = if layout.align() <= GC_MIN_ALIGN {
+			unsafe { nix_raw::GC_malloc(layout.size()) }
+		} else {
+			unsafe { nix_raw::GC_memalign(layout.align(), layout.size()) }
+		};
+		ptr.cast()
+	}
+	unsafe fn dealloc(&self, _ptr: *mut u8, _layout: std::alloc::Layout) {
+		// No-op: the collector reclaims unreachable memory on its own schedule, explicitly
+		// freeing here would race with its concurrent/incremental marking.
+	}
+	unsafe fn realloc(&self, ptr: *mut u8, _layout: std::alloc::Layout, new_size: usize) -> *mut u8 {
+		ensure_gc_alloc_ready();
+		unsafe { nix_raw::GC_realloc(ptr.cast(), new_size) }.cast()
+	}
+}
+
+#[cfg(feature = "boehm-gc-alloc")]
+#[global_allocator]
+static GC: GcAlloc = GcAlloc;
+
+/// Pauses the collector for its lifetime, dropping back to [`nix_raw::GC_enable`] on drop.
+/// Wrap hot sections that allocate heavily and don't want to pay for a collection in the
+/// middle (e.g. around [`Value::eval`]/[`FlakeReference::lock`]) with this when the
+/// `boehm-gc-alloc` feature is enabled.
+#[cfg(feature = "boehm-gc-alloc")]
+pub struct GcDisabledGuard(());
+
+#[cfg(feature = "boehm-gc-alloc")]
+impl GcDisabledGuard {
+	#[allow(clippy::new_without_default)]
+	pub fn new() -> Self {
+		unsafe { nix_raw::GC_disable() };
+		Self(())
+	}
+}
+
+#[cfg(feature = "boehm-gc-alloc")]
+impl Drop for GcDisabledGuard {
+	fn drop(&mut self) {
+		unsafe { nix_raw::GC_enable() };
+	}
+}
+
+#[cfg(any(
+	all(feature = "alloc-system", feature = "alloc-rpmalloc"),
+	all(feature = "alloc-system", feature = "boehm-gc-alloc"),
+	all(feature = "alloc-rpmalloc", feature = "boehm-gc-alloc"),
+))]
+compile_error!(
+	"`alloc-system`, `alloc-rpmalloc` and `boehm-gc-alloc` each install a `#[global_allocator]` \
+	 and are mutually exclusive; enable exactly one"
+);
+
+/// Thread-caching allocator for orchestration-only binaries: those that drive `Tf`/store
+/// transfers/etc. and never call [`Value::eval`] or [`FlakeReference::lock`], so there's no
+/// `libexpr`-owned memory for a collector to ever need to scan. Swapping this in instead of
+/// `boehm-gc-alloc` skips conservative-scan overhead entirely for that workload.
+#[cfg(feature = "alloc-rpmalloc")]
+#[global_allocator]
+static ALLOC: rpmalloc::RpMalloc = rpmalloc::RpMalloc;
+
+/// Plain `std::alloc::System`, for orchestration-only binaries that want to opt out of the GC
+/// allocator without pulling in `rpmalloc`. See [`ALLOC`](self) under `alloc-rpmalloc` for why
+/// this is only sound when the process never touches [`NixContext`]/`GLOBAL_STATE`.
+#[cfg(feature = "alloc-system")]
+#[global_allocator]
+static ALLOC: std::alloc::System = std::alloc::System;
+
+/// Panics if the process was built with a non-GC global allocator, for call sites that are
+/// about to hand the collector a `Value`-reachable pointer it needs to be able to scan.
+/// `alloc-system`/`alloc-rpmalloc` are only sound for binaries that never reach these call
+/// sites in the first place; this is the last line of defense if one does anyway, since the
+/// failure mode otherwise is a silently-collected live object rather than a clean panic.
+#[cfg(any(feature = "alloc-system", feature = "alloc-rpmalloc"))]
+fn assert_gc_alloc_active() {
+	panic!(
+		"this thread is touching NixContext/GLOBAL_STATE under a non-GC global allocator \
+		 (`alloc-system`/`alloc-rpmalloc`); rebuild with the `boehm-gc-alloc` feature for any \
+		 binary that evaluates Nix expressions"
+	);
+}
+#[cfg(not(any(feature = "alloc-system", feature = "alloc-rpmalloc")))]
+fn assert_gc_alloc_active() {}
@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Arguments;
+use std::path::Path;
 use std::sync::{LazyLock, Mutex};
 
 use cxx::ExternType;
+use serde::{Deserialize, Serialize};
 use tracing::{
 	Level, Span, debug, debug_span, error, error_span, info, info_span, trace, trace_span, warn,
 	warn_span,
@@ -113,67 +115,11 @@ impl ActivityType {
 			(ActivityType::CopyPaths, []) => {
 				debug_span!(target: "nix::copy-paths", "copying paths")
 			}
-			(ActivityType::Unknown, [])
-				if s.starts_with("copying \"") && s.ends_with("\" to the store") =>
-			{
-				let tree = s
-					.trim_start_matches("copying \"")
-					.trim_end_matches("\" to the store");
-				debug_span!(target: "nix::trees", "copying", tree)
-			}
-			(ActivityType::Unknown, [])
-				if s.starts_with("copying '") && s.ends_with("' to the store") =>
-			{
-				let tree = s
-					.trim_start_matches("copying '")
-					.trim_end_matches("' to the store");
-				debug_span!(target: "nix::trees", "copying", tree)
-			}
-			(ActivityType::Unknown, []) if s.starts_with("hashing '") && s.ends_with("'") => {
-				let tree = s.trim_start_matches("hashing '").trim_end_matches("'");
-				debug_span!(target: "nix::trees", "hashing", tree)
-			}
-			(ActivityType::Unknown, []) if s.starts_with("connecting to '") && s.ends_with("'") => {
-				let host = s
-					.trim_start_matches("connecting to '")
-					.trim_end_matches("'");
-				debug_span!(target: "nix::remote", "connecting", host)
-			}
-			(ActivityType::Unknown, [])
-				if s.starts_with("copying outputs from '") && s.ends_with("'") =>
-			{
-				let host = s
-					.trim_start_matches("copying outputs from '")
-					.trim_end_matches("'");
-				debug_span!(target: "nix::remote", "copying outputs", host)
-			}
-			(ActivityType::Unknown, [])
-				if s.starts_with("copying dependencies to '") && s.ends_with("'") =>
-			{
-				let host = s
-					.trim_start_matches("copying dependencies to '")
-					.trim_end_matches("'");
-				debug_span!(target: "nix::remote", "copying dependencies", host)
-			}
-			(ActivityType::Unknown, [])
-				if s.starts_with("waiting for the upload lock to '") && s.ends_with("'") =>
-			{
-				let host = s
-					.trim_start_matches("waiting for the upload lock to '")
-					.trim_end_matches("'");
-				debug_span!(target: "nix::remote", "waiting for upload lock", host)
-			}
-			(ActivityType::BuildWaiting, [])
-				if s.starts_with("waiting for a machine to build '") && s.ends_with("'") =>
-			{
-				let drv = parse_drv(
-					s.trim_start_matches("waiting for a machine to build '")
-						.trim_end_matches("'"),
-				);
-				debug_span!(target: "nix::build-waiting", "waiting for available builder", drv)
-			}
-			(ActivityType::Unknown, []) if s == "querying info about missing paths" => {
-				debug_span!(target: "nix::remote", "querying")
+			(ActivityType::Unknown | ActivityType::BuildWaiting, []) => {
+				match activity_rules().iter().find_map(|r| r.try_match(s)) {
+					Some(matched) => into(format_args!("{matched}")),
+					None => into(format_args!("{}({values:?})", self.name())),
+				}
 			}
 			_ => into(format_args!("{}({values:?})", self.name())),
 		}
@@ -200,6 +146,98 @@ impl ActivityType {
 			}
 		}
 	}
+	/// The `drv`/`host` (or similar) label to put on this activity's node in the DOT graph,
+	/// reusing the same field shapes [`Self::format`] matches on.
+	fn graph_label(&self, fields: &[FieldValue]) -> Option<String> {
+		use FieldValue::*;
+		match (self, fields) {
+			(ActivityType::QueryPathInfo, [Str(drv), Str(host)])
+			| (ActivityType::Substitute, [Str(drv), Str(host)]) => Some(format!(
+				"{}\\n{}",
+				escape_dot_label(parse_drv(drv)),
+				escape_dot_label(parse_host(host))
+			)),
+			(ActivityType::CopyPath, [Str(drv), Str(from), Str(to)]) => Some(format!(
+				"{}\\n{} -> {}",
+				escape_dot_label(parse_drv(drv)),
+				escape_dot_label(parse_host(from)),
+				escape_dot_label(parse_host(to))
+			)),
+			(ActivityType::Build, [Str(drv), Str(host), Int(_), Int(_)]) => Some(format!(
+				"{}\\n{}",
+				escape_dot_label(parse_drv(drv)),
+				escape_dot_label(parse_host(host))
+			)),
+			(ActivityType::FileTransfer, [Str(file)]) => {
+				Some(escape_dot_label(parse_path(file)))
+			}
+			_ => None,
+		}
+	}
+	/// The remote builder/substituter this activity talks to, if any, for the
+	/// `nix.activity.host` metric attribute (see [`StartActivityBuilder::emit`]).
+	fn graph_host(&self, fields: &[FieldValue]) -> Option<String> {
+		use FieldValue::*;
+		match (self, fields) {
+			(ActivityType::QueryPathInfo, [Str(_), Str(host)])
+			| (ActivityType::Substitute, [Str(_), Str(host)])
+			| (ActivityType::Build, [Str(_), Str(host), Int(_), Int(_)]) => {
+				Some(parse_host(host).to_owned())
+			}
+			(ActivityType::CopyPath, [Str(_), Str(_from), Str(to)]) => {
+				Some(parse_host(to).to_owned())
+			}
+			_ => None,
+		}
+	}
+	/// All of this activity's parsed structured fields (`drv`, `host`, `from`, `to`, `file`),
+	/// named for the JSON Lines sink (see [`jsonl`]). Falls back to matching
+	/// [`activity_rules`] for `Unknown`/`BuildWaiting` messages, same as [`Self::format`].
+	fn json_fields(&self, fields: &[FieldValue], s: &str) -> Vec<(String, String)> {
+		use FieldValue::*;
+		match (self, fields) {
+			(ActivityType::QueryPathInfo, [Str(drv), Str(host)])
+			| (ActivityType::Substitute, [Str(drv), Str(host)]) => vec![
+				("drv".to_owned(), parse_drv(drv).to_owned()),
+				("host".to_owned(), parse_host(host).to_owned()),
+			],
+			(ActivityType::CopyPath, [Str(drv), Str(from), Str(to)]) => vec![
+				("drv".to_owned(), parse_drv(drv).to_owned()),
+				("from".to_owned(), parse_host(from).to_owned()),
+				("to".to_owned(), parse_host(to).to_owned()),
+			],
+			(ActivityType::Build, [Str(drv), Str(host), Int(_), Int(_)]) => vec![
+				("drv".to_owned(), parse_drv(drv).to_owned()),
+				("host".to_owned(), parse_host(host).to_owned()),
+			],
+			(ActivityType::FileTransfer, [Str(file)]) => {
+				vec![("file".to_owned(), parse_path(file).to_owned())]
+			}
+			(ActivityType::Unknown | ActivityType::BuildWaiting, []) => activity_rules()
+				.iter()
+				.find_map(|r| r.extract(s))
+				.unwrap_or_default(),
+			_ => vec![],
+		}
+	}
+	/// Fill color for this activity's node, grouping the graph into builds, substitute/copy
+	/// traffic, and fetches so the shape of a run is visible at a glance.
+	fn dot_color(&self) -> &'static str {
+		match self {
+			ActivityType::Build | ActivityType::Builds | ActivityType::BuildWaiting => {
+				"lightblue"
+			}
+			ActivityType::Substitute
+			| ActivityType::CopyPath
+			| ActivityType::CopyPaths
+			| ActivityType::VerifyPaths
+			| ActivityType::OptimiseStore => "lightyellow",
+			ActivityType::FetchTree | ActivityType::FileTransfer | ActivityType::QueryPathInfo => {
+				"lightgreen"
+			}
+			ActivityType::Realise | ActivityType::PostBuildHook | ActivityType::Unknown => "white",
+		}
+	}
 }
 
 #[derive(Debug)]
@@ -217,6 +255,20 @@ enum ResultType {
 	Unknown = 999,
 }
 impl ResultType {
+	fn name(&self) -> &'static str {
+		match self {
+			ResultType::FileLinked => "file-linked",
+			ResultType::BuildLogLine => "build-log-line",
+			ResultType::UntrustedPath => "untrusted-path",
+			ResultType::CorruptedPath => "corrupted-path",
+			ResultType::SetPhase => "set-phase",
+			ResultType::Progress => "progress",
+			ResultType::SetExpected => "set-expected",
+			ResultType::PostBuildLogLine => "post-build-log-line",
+			ResultType::FetchStatus => "fetch-status",
+			ResultType::Unknown => "unknown",
+		}
+	}
 	fn from_int(v: u32) -> Self {
 		match v {
 			100 => Self::FileLinked,
@@ -262,6 +314,18 @@ impl From<Verbosity> for tracing::Level {
 	}
 }
 impl Verbosity {
+	fn name(&self) -> &'static str {
+		match self {
+			Verbosity::Error => "error",
+			Verbosity::Warn => "warn",
+			Verbosity::Notice => "notice",
+			Verbosity::Info => "info",
+			Verbosity::Talkative => "talkative",
+			Verbosity::Chatty => "chatty",
+			Verbosity::Debug => "debug",
+			Verbosity::Vomit => "vomit",
+		}
+	}
 	fn from_int(u: u32) -> Self {
 		[
 			Self::Error,
@@ -285,6 +349,385 @@ impl Verbosity {
 static NIX_SPAN_MAPPING: LazyLock<Mutex<HashMap<u64, Span>>> =
 	LazyLock::new(|| Mutex::new(HashMap::new()));
 
+/// How to post-process the text an [`ActivityRule`] captures between its `prefix` and
+/// `suffix` before binding it to a field name. `Drv`/`Host` reuse [`parse_drv`]/[`parse_host`]
+/// for the same store-path/hostname cleanup the structured arms of [`ActivityType::format`]
+/// already do.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum FieldParse {
+	#[default]
+	Raw,
+	Drv,
+	Host,
+}
+
+/// One entry of the activity-message rule table: turns an `ActivityType::Unknown`/
+/// `BuildWaiting` message matching `prefix`+`suffix` into a labeled span, without requiring a
+/// recompile when Nix changes its wording. See [`ACTIVITY_RULES_ENV`].
+#[derive(Debug, Deserialize)]
+struct ActivityRule {
+	/// Tracing target to group this rule's spans under, e.g. `"nix::remote"`.
+	target: String,
+	/// Span verb, e.g. `"connecting"`.
+	verb: String,
+	prefix: String,
+	suffix: String,
+	/// Names for the field(s) extracted from the text between `prefix` and `suffix`. A single
+	/// name captures the whole middle segment; more than one splits it on `delimiter`.
+	#[serde(default)]
+	fields: Vec<String>,
+	#[serde(default = "default_field_delimiter")]
+	delimiter: String,
+	#[serde(default)]
+	parse: FieldParse,
+}
+
+fn default_field_delimiter() -> String {
+	" ".to_owned()
+}
+
+impl ActivityRule {
+	/// Extracts this rule's declared field name/value pairs from `s`, if it matches its
+	/// `prefix`/`suffix`. Shared by [`Self::try_match`] (for the rendered span message) and
+	/// the JSON Lines sink (for structured fields on `Unknown`/`BuildWaiting` activities).
+	fn extract(&self, s: &str) -> Option<Vec<(String, String)>> {
+		let mid = strip_prefix_suffix(s, &self.prefix, &self.suffix)?;
+		let mid = match self.parse {
+			FieldParse::Raw => mid,
+			FieldParse::Drv => parse_drv(mid),
+			FieldParse::Host => parse_host(mid),
+		};
+
+		Some(match self.fields.as_slice() {
+			[] => vec![],
+			[name] => vec![(name.clone(), mid.to_owned())],
+			names => names
+				.iter()
+				.zip(mid.split(self.delimiter.as_str()))
+				.map(|(name, part)| (name.clone(), part.to_owned()))
+				.collect(),
+		})
+	}
+	/// Tries to match `s`, returning the rendered `target(verb, field=value, ...)` text on
+	/// success. Tracing spans need their field names fixed at the macro callsite, so a
+	/// dynamically-configured field can't become a real structured span field the way `drv`/
+	/// `host` do above; this renders into the same kind of message [`ActivityType::format`]'s
+	/// catch-all arm already produces for shapes it doesn't special-case.
+	fn try_match(&self, s: &str) -> Option<String> {
+		let fields = self.extract(s)?;
+		let mut out = format!("{}({}", self.target, self.verb);
+		for (name, value) in &fields {
+			out.push_str(&format!(", {name}={value}"));
+		}
+		out.push(')');
+		Some(out)
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct ActivityRules {
+	/// Bumped on breaking changes to the rule schema, so a future loader can migrate an
+	/// older on-disk file instead of failing outright.
+	#[allow(dead_code)]
+	version: u32,
+	#[serde(default)]
+	rule: Vec<ActivityRule>,
+}
+
+/// Built-in rules, covering the same messages the hardcoded `starts_with`/`ends_with` arms
+/// used to. Kept as the default so the crate works unmodified; point [`ACTIVITY_RULES_ENV`] at
+/// a file to use a different table without a recompile. The file's rules replace these
+/// entirely; they are not merged with them.
+const DEFAULT_ACTIVITY_RULES_TOML: &str = r#"
+version = 1
+
+[[rule]]
+target = "nix::trees"
+verb = "copying"
+prefix = "copying \""
+suffix = "\" to the store"
+fields = ["tree"]
+
+[[rule]]
+target = "nix::trees"
+verb = "copying"
+prefix = "copying '"
+suffix = "' to the store"
+fields = ["tree"]
+
+[[rule]]
+target = "nix::trees"
+verb = "hashing"
+prefix = "hashing '"
+suffix = "'"
+fields = ["tree"]
+
+[[rule]]
+target = "nix::remote"
+verb = "connecting"
+prefix = "connecting to '"
+suffix = "'"
+fields = ["host"]
+
+[[rule]]
+target = "nix::remote"
+verb = "copying outputs"
+prefix = "copying outputs from '"
+suffix = "'"
+fields = ["host"]
+
+[[rule]]
+target = "nix::remote"
+verb = "copying dependencies"
+prefix = "copying dependencies to '"
+suffix = "'"
+fields = ["host"]
+
+[[rule]]
+target = "nix::remote"
+verb = "waiting for upload lock"
+prefix = "waiting for the upload lock to '"
+suffix = "'"
+fields = ["host"]
+
+[[rule]]
+target = "nix::build-waiting"
+verb = "waiting for available builder"
+prefix = "waiting for a machine to build '"
+suffix = "'"
+fields = ["drv"]
+parse = "drv"
+
+[[rule]]
+target = "nix::remote"
+verb = "querying"
+prefix = "querying info about missing paths"
+suffix = ""
+"#;
+
+/// Env var naming a TOML file to load the activity-message rule table from, in place of
+/// [`DEFAULT_ACTIVITY_RULES_TOML`]; unset or unreadable falls back to the built-in rules.
+const ACTIVITY_RULES_ENV: &str = "FLEET_NIX_ACTIVITY_RULES";
+
+static ACTIVITY_RULES: LazyLock<Vec<ActivityRule>> = LazyLock::new(|| {
+	let loaded = std::env::var_os(ACTIVITY_RULES_ENV).and_then(|path| {
+		match std::fs::read_to_string(&path) {
+			Ok(data) => Some(data),
+			Err(err) => {
+				warn!("failed to read {ACTIVITY_RULES_ENV} ({path:?}): {err}, using built-in activity rules");
+				None
+			}
+		}
+	});
+	let data = loaded.as_deref().unwrap_or(DEFAULT_ACTIVITY_RULES_TOML);
+	match toml::from_str::<ActivityRules>(data) {
+		Ok(rules) => rules.rule,
+		Err(err) => {
+			warn!("failed to parse activity rule table: {err}, using built-in activity rules");
+			toml::from_str::<ActivityRules>(DEFAULT_ACTIVITY_RULES_TOML)
+				.expect("built-in activity rules should parse")
+				.rule
+		}
+	}
+});
+
+fn activity_rules() -> &'static [ActivityRule] {
+	&ACTIVITY_RULES
+}
+
+/// One node of the activity tree kept around for [`flush_activity_dot`], since the `Span`s in
+/// [`NIX_SPAN_MAPPING`] are discarded as soon as their activity stops. Also doubles as the
+/// lookup [`StartActivityBuilder::emit_result`] uses to attribute OTLP metrics back to an
+/// activity's type/host once it only has an `activity_id` to go on (see [`otlp_metrics`]).
+struct NodeInfo {
+	type_name: &'static str,
+	label: Option<String>,
+	color: &'static str,
+	host: Option<String>,
+}
+
+/// OpenTelemetry instruments fed by Nix's `Progress`/`SetExpected` results, guarded by the
+/// `otlp` feature. Reuses the `MetricExporter` that [`opentelemetry_exporter_env`]'s
+/// `OtlpMetricsSettings` already targets — this module just needs a `MeterProvider` to be
+/// installed globally (`opentelemetry::global::set_meter_provider`) for these to go anywhere.
+#[cfg(feature = "otlp")]
+mod otlp_metrics {
+	use std::collections::HashMap;
+	use std::sync::{LazyLock, Mutex};
+
+	use opentelemetry::KeyValue;
+	use opentelemetry::metrics::{Counter, UpDownCounter};
+
+	pub(super) struct Metrics {
+		/// In-flight activities, by type/host: `+1` in `emit`, `-1` in `emit_stop`.
+		pub(super) in_flight: UpDownCounter<i64>,
+		/// Completed progress units (bytes downloaded, paths copied, ...), as reported by
+		/// `Progress`'s `done` field.
+		pub(super) completed: Counter<u64>,
+		/// Expected progress units for in-flight activities, as reported by `Progress`'s
+		/// `expected` field and `SetExpected`.
+		pub(super) expected: UpDownCounter<i64>,
+	}
+
+	pub(super) static METRICS: LazyLock<Metrics> = LazyLock::new(|| {
+		let meter = opentelemetry::global::meter("nix");
+		Metrics {
+			in_flight: meter
+				.i64_up_down_counter("nix.activity.in_flight")
+				.with_description("In-flight Nix activities, by activity type and remote host")
+				.build(),
+			completed: meter
+				.u64_counter("nix.activity.completed_units")
+				.with_description(
+					"Completed progress units reported by Nix activities (download bytes, \
+					 copied paths, finished builds, ...)",
+				)
+				.build(),
+			expected: meter
+				.i64_up_down_counter("nix.activity.expected_units")
+				.with_description("Expected progress units for in-flight Nix activities")
+				.build(),
+		}
+	});
+
+	/// Last `(done, expected)` reported for each in-flight activity, so a new `Progress`/
+	/// `SetExpected` report can be turned into a delta for the counter/up-down-counters above.
+	pub(super) static PROGRESS: LazyLock<Mutex<HashMap<u64, (i64, i64)>>> =
+		LazyLock::new(|| Mutex::new(HashMap::new()));
+
+	pub(super) fn attrs(type_name: &'static str, host: Option<&str>) -> Vec<KeyValue> {
+		let mut attrs = vec![KeyValue::new("nix.activity.type", type_name)];
+		if let Some(host) = host {
+			attrs.push(KeyValue::new("nix.activity.host", host.to_owned()));
+		}
+		attrs
+	}
+}
+
+#[derive(Default)]
+struct ActivityGraph {
+	nodes: HashMap<u64, NodeInfo>,
+	edges: Vec<(u64, u64)>,
+}
+
+static ACTIVITY_GRAPH: LazyLock<Mutex<ActivityGraph>> =
+	LazyLock::new(|| Mutex::new(ActivityGraph::default()));
+
+fn escape_dot_label(s: &str) -> String {
+	s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Optional JSON Lines sink for every parsed activity/result, toggled by
+/// `FLEET_NIX_ACTIVITY_JSONL` (a file path, or `fd:<n>` for an already-open file descriptor),
+/// so downstream tooling can consume Fleet's Nix progress without scraping formatted terminal
+/// output. One compact object per [`StartActivityBuilder::emit`]/`emit_result`/`emit_stop`
+/// call, preserving the same activity-tree semantics [`NIX_SPAN_MAPPING`] already knows about.
+mod jsonl {
+	use std::fs::{File, OpenOptions};
+	use std::io::Write;
+	use std::os::fd::{FromRawFd, RawFd};
+	use std::sync::{LazyLock, Mutex};
+
+	use serde::Serialize;
+	use tracing::warn;
+
+	const ENV: &str = "FLEET_NIX_ACTIVITY_JSONL";
+
+	static SINK: LazyLock<Option<Mutex<Box<dyn Write + Send>>>> = LazyLock::new(|| {
+		let target = std::env::var(ENV).ok()?;
+		let writer: Box<dyn Write + Send> = if let Some(fd) = target.strip_prefix("fd:") {
+			match fd.parse::<RawFd>() {
+				Ok(fd) => Box::new(unsafe { File::from_raw_fd(fd) }),
+				Err(err) => {
+					warn!("invalid {ENV} file descriptor {fd:?}: {err}, disabling JSONL activity sink");
+					return None;
+				}
+			}
+		} else {
+			match OpenOptions::new().create(true).append(true).open(&target) {
+				Ok(file) => Box::new(file),
+				Err(err) => {
+					warn!("failed to open {ENV} target {target:?}: {err}, disabling JSONL activity sink");
+					return None;
+				}
+			}
+		};
+		Some(Mutex::new(writer))
+	});
+
+	pub(super) fn emit(value: &impl Serialize) {
+		let Some(sink) = SINK.as_ref() else { return };
+		let Ok(mut line) = serde_json::to_vec(value) else {
+			return;
+		};
+		line.push(b'\n');
+		let mut sink = sink.lock().expect("not poisoned");
+		let _ = sink.write_all(&line);
+	}
+}
+
+#[derive(Serialize)]
+struct JsonlStart<'a> {
+	event: &'static str,
+	activity_id: u64,
+	parent: u64,
+	#[serde(rename = "type")]
+	typ: &'static str,
+	verbosity: &'static str,
+	message: &'a str,
+	fields: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Default)]
+struct JsonlResult<'a> {
+	event: &'static str,
+	activity_id: u64,
+	result: &'static str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	phase: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	done: Option<i64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	expected: Option<i64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	line: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JsonlStop {
+	event: &'static str,
+	activity_id: u64,
+}
+
+/// Writes the activity tree recorded since startup as a Graphviz `digraph`, with one node per
+/// Nix activity (colored by category: builds, substitute/copy, or fetch) and one edge per
+/// parent/child relationship reported by the logging bridge. Call this on final stop or
+/// program exit, after which `NIX_SPAN_MAPPING`'s spans are gone but the tree recorded here
+/// survives.
+pub fn flush_activity_dot(path: impl AsRef<Path>) -> std::io::Result<()> {
+	let graph = ACTIVITY_GRAPH.lock().expect("not poisoned");
+
+	let mut out = String::from("digraph nix {\n");
+	for (id, node) in &graph.nodes {
+		let label = match &node.label {
+			Some(label) => format!("{}\\n{label}", node.type_name),
+			None => node.type_name.to_string(),
+		};
+		out.push_str(&format!(
+			"\tn{id} [label=\"{label}\", style=filled, fillcolor=\"{}\"];\n",
+			node.color
+		));
+	}
+	let edges: HashSet<(u64, u64)> = graph.edges.iter().copied().collect();
+	for (parent, child) in edges {
+		out.push_str(&format!("\tn{parent} -> n{child};\n"));
+	}
+	out.push_str("}\n");
+
+	std::fs::write(path, out)
+}
+
 #[derive(Debug)]
 enum FieldValue {
 	Int(i32),
@@ -306,6 +749,38 @@ impl StartActivityBuilder {
 		self.fields.push(FieldValue::Str(v.to_string()));
 	}
 	fn emit(&mut self, parent: u64, s: &str) {
+		let host = self.typ.graph_host(&self.fields);
+		{
+			let mut graph = ACTIVITY_GRAPH.lock().expect("not poisoned");
+			if parent != 0 && graph.nodes.contains_key(&parent) {
+				graph.edges.push((parent, self.activity_id));
+			}
+			graph.nodes.insert(
+				self.activity_id,
+				NodeInfo {
+					type_name: self.typ.name(),
+					label: self.typ.graph_label(&self.fields),
+					color: self.typ.dot_color(),
+					host: host.clone(),
+				},
+			);
+		}
+
+		#[cfg(feature = "otlp")]
+		otlp_metrics::METRICS
+			.in_flight
+			.add(1, &otlp_metrics::attrs(self.typ.name(), host.as_deref()));
+
+		jsonl::emit(&JsonlStart {
+			event: "start",
+			activity_id: self.activity_id,
+			parent,
+			typ: self.typ.name(),
+			verbosity: self.verbosity.name(),
+			message: s,
+			fields: self.typ.json_fields(&self.fields, s).into_iter().collect(),
+		});
+
 		let mut mapping = NIX_SPAN_MAPPING.lock().expect("not poisoned");
 
 		let parent = mapping.get(&parent);
@@ -418,23 +893,100 @@ impl StartActivityBuilder {
 			// ResultType::FileLinked => todo!(),
 			(ResultType::BuildLogLine, [Str(s)]) => {
 				let s = ansi_filter(s);
+				jsonl::emit(&JsonlResult {
+					event: "result",
+					activity_id: self.activity_id,
+					result: res.name(),
+					line: Some(s.as_str()),
+					..Default::default()
+				});
 				info!("{s}");
 			}
 			// ResultType::UntrustedPath => todo!(),
 			// ResultType::CorruptedPath => todo!(),
 			// ResultType::SetPhase => todo!(),
-			(ResultType::SetExpected, [Int(act_ty), Int(_expected)]) => {
-				let _act_ty = ActivityType::from_int(*act_ty as u32);
+			(ResultType::SetExpected, [Int(_act_ty_raw), Int(expected)]) => {
+				jsonl::emit(&JsonlResult {
+					event: "result",
+					activity_id: self.activity_id,
+					result: res.name(),
+					expected: Some(*expected as i64),
+					..Default::default()
+				});
+				#[cfg(feature = "otlp")]
+				{
+					let act_ty = ActivityType::from_int(*_act_ty_raw as u32);
+					let host = ACTIVITY_GRAPH
+						.lock()
+						.expect("not poisoned")
+						.nodes
+						.get(&self.activity_id)
+						.and_then(|n| n.host.clone());
+					let attrs = otlp_metrics::attrs(act_ty.name(), host.as_deref());
+
+					let mut progress = otlp_metrics::PROGRESS.lock().expect("not poisoned");
+					let (_, prev_expected) =
+						progress.entry(self.activity_id).or_insert((0, 0));
+					let delta = (*expected as i64) - *prev_expected;
+					*prev_expected = *expected as i64;
+					drop(progress);
+
+					if delta != 0 {
+						otlp_metrics::METRICS.expected.add(delta, &attrs);
+					}
+				}
 			}
 			(ResultType::SetPhase, [Str(phase)]) => {
+				jsonl::emit(&JsonlResult {
+					event: "result",
+					activity_id: self.activity_id,
+					result: res.name(),
+					phase: Some(phase.as_str()),
+					..Default::default()
+				});
 				// parent.pb_set_message(phase);
 				debug!(target: "nix::phase", phase)
 			}
-			(ResultType::Progress, [Int(_done), Int(_expected), Int(_), Int(_)]) => {
+			(ResultType::Progress, [Int(done), Int(expected), Int(_), Int(_)]) => {
+				jsonl::emit(&JsonlResult {
+					event: "result",
+					activity_id: self.activity_id,
+					result: res.name(),
+					done: Some(*done as i64),
+					expected: Some(*expected as i64),
+					..Default::default()
+				});
 				#[cfg(feature = "indicatif")]
 				{
-					parent.pb_set_length(*_expected as u64);
-					parent.pb_set_position(*_done as u64);
+					parent.pb_set_length(*expected as u64);
+					parent.pb_set_position(*done as u64);
+				}
+				#[cfg(feature = "otlp")]
+				{
+					let (type_name, host) = ACTIVITY_GRAPH
+						.lock()
+						.expect("not poisoned")
+						.nodes
+						.get(&self.activity_id)
+						.map(|n| (n.type_name, n.host.clone()))
+						.unwrap_or((self.typ.name(), None));
+					let attrs = otlp_metrics::attrs(type_name, host.as_deref());
+
+					let mut progress = otlp_metrics::PROGRESS.lock().expect("not poisoned");
+					let (prev_done, prev_expected) =
+						progress.entry(self.activity_id).or_insert((0, 0));
+					let done_delta = (*done as i64) - *prev_done;
+					let expected_delta = (*expected as i64) - *prev_expected;
+					*prev_done = *done as i64;
+					*prev_expected = *expected as i64;
+					drop(progress);
+
+					if done_delta > 0 {
+						otlp_metrics::METRICS.completed.add(done_delta as u64, &attrs);
+					}
+					if expected_delta != 0 {
+						otlp_metrics::METRICS.expected.add(expected_delta, &attrs);
+					}
 				}
 			}
 			_ => warn!("unknown progress report: {:?}({:?})", &res, &self.fields),
@@ -456,6 +1008,21 @@ fn emit_warn(v: &str) {
 fn emit_stop(v: u64) {
 	let mut mapping = NIX_SPAN_MAPPING.lock().expect("not poisoned");
 	mapping.remove(&v);
+
+	jsonl::emit(&JsonlStop {
+		event: "stop",
+		activity_id: v,
+	});
+
+	#[cfg(feature = "otlp")]
+	{
+		if let Some(node) = ACTIVITY_GRAPH.lock().expect("not poisoned").nodes.get(&v) {
+			otlp_metrics::METRICS
+				.in_flight
+				.add(-1, &otlp_metrics::attrs(node.type_name, node.host.as_deref()));
+		}
+		otlp_metrics::PROGRESS.lock().expect("not poisoned").remove(&v);
+	}
 }
 fn emit_log(lvl: u32, v: &[u8]) {
 	let verbosity = Verbosity::from_int(lvl);
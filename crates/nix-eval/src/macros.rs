@@ -1,60 +1,41 @@
-#[macro_export]
-macro_rules! nix_expr_inner {
-	//(@munch_object FIXME: value should be arbitrary nix_expr_inner input... Time to write proc-macro?
-	(@obj($o:ident) $field:ident$(, $($tt:tt)*)?) => {{
-		$o.insert(
-			stringify!($field),
-			$crate::Value::from($field),
-		);
-		$(nix_expr_inner!(@obj($o) $($tt)*);)?
-	}};
-	(@obj($o:ident) $field:ident: $v:expr$(, $($tt:tt)*)?) => {{
-		$o.insert(
-			stringify!($field),
-			$crate::Value::from($v),
-		);
-		$(nix_expr_inner!(@obj($o) $($tt)*);)?
-	}};
-	(@obj($o:ident)) => {{}};
-	(Obj { $($tt:tt)* }) => {{
-		use $crate::{nix_expr_inner};
-		let mut out = std::collections::hash_map::HashMap::new();
-		nix_expr_inner!(@obj(out) $($tt)*);
-		Value::new_attrs(out)?
-	}};
-	(@field($o:ident) . $var:ident $($tt:tt)*) => {{
-		$o.index_attr(stringify!($var));
-		nix_expr_inner!(@field($o) $($tt)*);
-	}};
-	(@field($o:ident) [{ $v:expr }] $($tt:tt)*) => {{
-		$o.push(Index::attr(&$v));
-		nix_expr_inner!(@o($o) $($tt)*);
-	}};
-	(@field($o:ident) [ $($var:tt)+ ] $($tt:tt)*) => {{
-		$o.push(Index::Expr($crate::nix_expr_inner!($($var)+)));
-		nix_expr_inner!(@o($o) $($tt)*);
-	}};
-	(@field($o:ident) ($($var:tt)*) $($tt:tt)*) => {
-		$o.push(Index::ExprApply($crate::nix_expr_inner!($($var)+)));
-		nix_expr_inner!(@o($o) $($tt)*);
-	};
-	(@field($o:ident)) => {};
-	($field:ident $($tt:tt)*) => {{
-		use $crate::{nix_expr_inner};
-		// might be used if indexed
-		#[allow(unused_mut)]
-		let mut out = $field.clone();
-		nix_expr_inner!(@field(out) $($tt)*);
-		out
-	}};
-	($v:literal) => {{
-		use $crate::macros::NixExprBuilder;
-		NixExprBuilder::string($v)
-	}};
-	({$v:expr}) => {{
-		$crate::Value::serialized(&$v)?
-	}}
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::Value;
+
+/// A byte buffer to splice into a `nix_expr!`/`nix_go!` `{ .. }` antiquotation as a Nix
+/// byte string. Wrap raw, possibly non-UTF-8 data (derivation ATerm env values,
+/// `std::process::Command` output, ...) in this before interpolating it, so it's
+/// rendered through [`Value::serialized_bytes`] instead of the `Serialize`-based
+/// [`Value::serialized`], which requires valid UTF-8.
+pub struct RawBytes(pub Vec<u8>);
+
+impl From<Vec<u8>> for RawBytes {
+	fn from(v: Vec<u8>) -> Self {
+		Self(v)
+	}
+}
+
+/// What `nix_expr_inner!`'s `{ expr }` antiquotation calls to turn the interpolated
+/// Rust value into a `Value`. Implemented generically for any `Serialize` type (via
+/// [`Value::serialized`]), with a dedicated impl for [`RawBytes`] since it deliberately
+/// doesn't implement `Serialize`.
+pub trait IntoNixExprValue {
+	fn into_nix_expr_value(&self) -> Result<Value>;
 }
+
+impl<T: Serialize> IntoNixExprValue for T {
+	fn into_nix_expr_value(&self) -> Result<Value> {
+		Value::serialized(self)
+	}
+}
+
+impl IntoNixExprValue for RawBytes {
+	fn into_nix_expr_value(&self) -> Result<Value> {
+		Value::serialized_bytes(&self.0)
+	}
+}
+
 #[macro_export]
 macro_rules! nix_expr {
 	($($tt:tt)+) => {{
@@ -0,0 +1,235 @@
+//! A small encoder/decoder for the netencode self-describing format, used to hand typed
+//! host/config data to external execline/netencode-style tools over stdin/argv, and to
+//! read their output back, as a compact alternative to [`Value::as_json`].
+//!
+//! Every value is a tag byte followed by a length-prefixed payload and a trailing `,`:
+//!
+//! - `u,` - unit
+//! - `i<len>:<digits>,` - signed integer, `<digits>` is its decimal ASCII form
+//! - `n<len>:<digits>,` - unsigned integer, for values that don't fit in an `i64`
+//! - `t<len>:<bytes>,` - UTF-8 text, `<len>` is the byte length of `<bytes>`
+//! - `[<len>:<items>,]` - a list, `<len>` is the byte length of the concatenated,
+//!   back-to-back encoded items
+//! - `{<len>:<fields>,}` - a record, `<len>` is the byte length of the concatenated
+//!   fields, each field itself a tagged value keyed by its field name (see below)
+//! - `<<len>:<tag>|<value>` - a tagged value: `<tag>` (`<len>` bytes) names `<value>`.
+//!   Record fields are tagged values keyed by field name; booleans are tagged units
+//!   (`<4:true|u,` / `<5:false|u,`), since the format has no dedicated boolean tag.
+//!
+//! There's no native tag for floating point, so floats are encoded as `t` text (their
+//! Rust `Display` form) to avoid silently truncating them to an integer.
+
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Number, Value as Json};
+
+use crate::Value;
+
+impl Value {
+	/// Evaluate `self` and render it as netencode bytes, via the same
+	/// `builtins.toJSON`-based path [`Self::as_json`] uses.
+	pub fn to_netencode(&self) -> Result<Vec<u8>> {
+		let json: Json = self.as_json()?;
+		let mut out = Vec::new();
+		encode(&json, &mut out);
+		Ok(out)
+	}
+
+	/// Parse netencode bytes into a [`Value`], by decoding to the JSON-equivalent
+	/// subset of the format and then evaluating it the same way [`Self::serialized`]
+	/// does.
+	pub fn from_netencode(bytes: &[u8]) -> Result<Self> {
+		let (json, rest) = decode(bytes).context("invalid netencode")?;
+		if !rest.is_empty() {
+			bail!("trailing {} byte(s) after netencode value", rest.len());
+		}
+		Self::serialized(&json)
+	}
+}
+
+fn encode_tagged(tag: &str, value_out: impl FnOnce(&mut Vec<u8>), out: &mut Vec<u8>) {
+	out.push(b'<');
+	out.extend(tag.len().to_string().as_bytes());
+	out.push(b':');
+	out.extend(tag.as_bytes());
+	out.push(b'|');
+	value_out(out);
+}
+
+fn encode(json: &Json, out: &mut Vec<u8>) {
+	match json {
+		Json::Null => out.extend(b"u,"),
+		Json::Bool(b) => {
+			let tag = if *b { "true" } else { "false" };
+			encode_tagged(tag, |out| out.extend(b"u,"), out);
+		}
+		Json::Number(n) => encode_number(n, out),
+		Json::String(s) => {
+			out.push(b't');
+			out.extend(s.len().to_string().as_bytes());
+			out.push(b':');
+			out.extend(s.as_bytes());
+			out.push(b',');
+		}
+		Json::Array(items) => {
+			let mut body = Vec::new();
+			for item in items {
+				encode(item, &mut body);
+			}
+			out.push(b'[');
+			out.extend(body.len().to_string().as_bytes());
+			out.push(b':');
+			out.extend(body);
+			out.push(b',');
+			out.push(b']');
+		}
+		Json::Object(fields) => {
+			let mut body = Vec::new();
+			for (k, v) in fields {
+				encode_tagged(k, |out| encode(v, out), &mut body);
+			}
+			out.push(b'{');
+			out.extend(body.len().to_string().as_bytes());
+			out.push(b':');
+			out.extend(body);
+			out.push(b',');
+			out.push(b'}');
+		}
+	}
+}
+
+fn encode_number(n: &Number, out: &mut Vec<u8>) {
+	if let Some(i) = n.as_i64() {
+		out.push(b'i');
+		let digits = i.to_string();
+		out.extend(digits.len().to_string().as_bytes());
+		out.push(b':');
+		out.extend(digits.as_bytes());
+		out.push(b',');
+	} else if let Some(u) = n.as_u64() {
+		out.push(b'n');
+		let digits = u.to_string();
+		out.extend(digits.len().to_string().as_bytes());
+		out.push(b':');
+		out.extend(digits.as_bytes());
+		out.push(b',');
+	} else {
+		// No native float tag; fall back to text so its digits survive instead of getting
+		// truncated. `from_netencode` reads such a value back as a string, not a number.
+		let s = n.to_string();
+		out.push(b't');
+		out.extend(s.len().to_string().as_bytes());
+		out.push(b':');
+		out.extend(s.as_bytes());
+		out.push(b',');
+	}
+}
+
+/// Parses a single netencode value off the front of `input`, returning it together with
+/// the unconsumed remainder.
+fn decode(input: &[u8]) -> Result<(Json, &[u8])> {
+	let (&tag, rest) = input.split_first().context("unexpected end of input")?;
+	match tag {
+		b'u' => {
+			let rest = expect_byte(rest, b',')?;
+			Ok((Json::Null, rest))
+		}
+		b'i' | b'n' => {
+			let (len, rest) = take_len(rest)?;
+			let rest = expect_byte(rest, b':')?;
+			let (digits, rest) = take_bytes(rest, len)?;
+			let digits = std::str::from_utf8(digits).context("non-utf8 integer digits")?;
+			let n = if tag == b'i' {
+				Number::from(digits.parse::<i64>().context("invalid `i` digits")?)
+			} else {
+				Number::from(digits.parse::<u64>().context("invalid `n` digits")?)
+			};
+			let rest = expect_byte(rest, b',')?;
+			Ok((Json::Number(n), rest))
+		}
+		b't' | b'b' => {
+			let (len, rest) = take_len(rest)?;
+			let rest = expect_byte(rest, b':')?;
+			let (bytes, rest) = take_bytes(rest, len)?;
+			let s = String::from_utf8_lossy(bytes).into_owned();
+			let rest = expect_byte(rest, b',')?;
+			Ok((Json::String(s), rest))
+		}
+		b'[' => {
+			let (len, rest) = take_len(rest)?;
+			let rest = expect_byte(rest, b':')?;
+			let (mut body, rest) = take_bytes(rest, len)?;
+			let mut items = Vec::new();
+			while !body.is_empty() {
+				let (item, remaining) = decode(body)?;
+				items.push(item);
+				body = remaining;
+			}
+			let rest = expect_byte(rest, b',')?;
+			let rest = expect_byte(rest, b']')?;
+			Ok((Json::Array(items), rest))
+		}
+		b'{' => {
+			let (len, rest) = take_len(rest)?;
+			let rest = expect_byte(rest, b':')?;
+			let (mut body, rest) = take_bytes(rest, len)?;
+			let mut fields = Map::new();
+			while !body.is_empty() {
+				let (key, value, remaining) = decode_tagged(body)?;
+				fields.insert(key, value);
+				body = remaining;
+			}
+			let rest = expect_byte(rest, b',')?;
+			let rest = expect_byte(rest, b'}')?;
+			Ok((Json::Object(fields), rest))
+		}
+		b'<' => {
+			let (tag, value, rest) = decode_tagged(input)?;
+			let value = match tag.as_str() {
+				"true" => Json::Bool(true),
+				"false" => Json::Bool(false),
+				_ => value,
+			};
+			Ok((value, rest))
+		}
+		other => bail!("unknown netencode tag {:?}", other as char),
+	}
+}
+
+/// Parses a `<<len>:<tag>|<value>` tagged value starting at `input[0] == '<'`.
+fn decode_tagged(input: &[u8]) -> Result<(String, Json, &[u8])> {
+	let rest = expect_byte(input, b'<')?;
+	let (len, rest) = take_len(rest)?;
+	let rest = expect_byte(rest, b':')?;
+	let (tag, rest) = take_bytes(rest, len)?;
+	let tag = std::str::from_utf8(tag).context("non-utf8 tag name")?.to_owned();
+	let rest = expect_byte(rest, b'|')?;
+	let (value, rest) = decode(rest)?;
+	Ok((tag, value, rest))
+}
+
+fn take_len(input: &[u8]) -> Result<(usize, &[u8])> {
+	let colon = input
+		.iter()
+		.position(|&b| b == b':')
+		.context("expected `:` after length prefix")?;
+	let len: usize = std::str::from_utf8(&input[..colon])
+		.context("non-utf8 length prefix")?
+		.parse()
+		.context("invalid length prefix")?;
+	Ok((len, &input[colon..]))
+}
+
+fn take_bytes(input: &[u8], len: usize) -> Result<(&[u8], &[u8])> {
+	if input.len() < len {
+		bail!("expected {len} byte(s), got {}", input.len());
+	}
+	Ok(input.split_at(len))
+}
+
+fn expect_byte(input: &[u8], expected: u8) -> Result<&[u8]> {
+	let (&b, rest) = input.split_first().context("unexpected end of input")?;
+	if b != expected {
+		bail!("expected {:?}, got {:?}", expected as char, b as char);
+	}
+	Ok(rest)
+}
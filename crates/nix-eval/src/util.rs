@@ -0,0 +1,69 @@
+//! Small helpers built on top of the core [`Value`] API.
+//!
+//! [`Value::par_map_fields`] is the one sanctioned way to evaluate/realize several fields of
+//! an attrset concurrently (e.g. building many `nixosConfigurations` or `packages.*` outputs
+//! at once). It exists specifically so that every thread touching a [`Value`] is registered
+//! with the Boehm collector via [`ThreadRegisterGuard`] - the blanket `unsafe impl Send`/
+//! `unsafe impl Sync` on [`Value`] (and friends) is only sound for GC-registered threads, and
+//! this is the entry point that guarantees that instead of trusting each caller to remember.
+
+use std::thread;
+
+use anyhow::anyhow;
+
+use crate::{Result, ThreadRegisterGuard, Value};
+
+impl Value {
+	/// Calls `f` for every field of this attrset, spread across a small pool of
+	/// GC-registered worker threads, instead of a serial `list_fields` + `get_field` loop.
+	///
+	/// Each worker constructs a [`ThreadRegisterGuard`] on entry and drops it once its share
+	/// of the fields is done, so `Value`s only ever cross into threads the collector knows
+	/// about. Results are returned in the same order as `list_fields`.
+	pub fn par_map_fields<T, F>(&self, f: F) -> Result<Vec<T>>
+	where
+		T: Send,
+		F: Fn(&str, Value) -> Result<T> + Sync,
+	{
+		let fields = self.list_fields()?;
+		if fields.is_empty() {
+			return Ok(Vec::new());
+		}
+
+		let workers = thread::available_parallelism()
+			.map(|n| n.get())
+			.unwrap_or(1)
+			.min(fields.len());
+		let chunk_size = fields.len().div_ceil(workers);
+
+		let mut results: Vec<Option<Result<T>>> = (0..fields.len()).map(|_| None).collect();
+
+		thread::scope(|scope| -> Result<()> {
+			let mut handles = Vec::with_capacity(workers);
+			for (names, slots) in fields
+				.chunks(chunk_size)
+				.zip(results.chunks_mut(chunk_size))
+			{
+				let f = &f;
+				let attrs = &*self;
+				handles.push(scope.spawn(move || {
+					let _guard = ThreadRegisterGuard::new();
+					for (name, slot) in names.iter().zip(slots.iter_mut()) {
+						*slot = Some(attrs.get_field(name).and_then(|v| f(name, v)));
+					}
+				}));
+			}
+			for handle in handles {
+				handle
+					.join()
+					.map_err(|_| anyhow!("evaluation worker thread panicked"))?;
+			}
+			Ok(())
+		})?;
+
+		results
+			.into_iter()
+			.map(|r| r.expect("every field slot is filled by its worker"))
+			.collect()
+	}
+}
@@ -0,0 +1,241 @@
+//! A `serde::Deserializer` that walks a [`Value`] structurally, so typed data can be pulled
+//! out of a Nix attrset without first rendering it to JSON via `builtins.toJSON` (see
+//! [`Value::as_json`]/[`Value::as_native`]).
+
+use std::fmt;
+
+use serde::de::{self, IntoDeserializer};
+
+use crate::{NixType, Value};
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct Error(String);
+impl de::Error for Error {
+	fn custom<T: fmt::Display>(msg: T) -> Self {
+		Self(msg.to_string())
+	}
+}
+impl From<anyhow::Error> for Error {
+	fn from(e: anyhow::Error) -> Self {
+		Self(e.to_string())
+	}
+}
+
+pub struct ValueDeserializer<'v>(pub &'v Value);
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_> {
+	type Error = Error;
+
+	fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+		match self.0.type_of() {
+			NixType::Thunk => Err(Error::custom("unexpected unevaluated thunk")),
+			NixType::Int => visitor.visit_i64(self.0.as_int()?),
+			NixType::Float => visitor.visit_f64(self.0.as_float()?),
+			NixType::Bool => visitor.visit_bool(self.0.as_bool()?),
+			NixType::String | NixType::Path => visitor.visit_string(self.0.to_string()?),
+			NixType::Null => visitor.visit_unit(),
+			NixType::Attrs => visitor.visit_map(AttrsAccess::new(self.0)?),
+			NixType::List => visitor.visit_seq(ListAccess::new(self.0)?),
+			NixType::Function => Err(Error::custom("cannot deserialize a function")),
+			NixType::External => Err(Error::custom("cannot deserialize an external value")),
+		}
+	}
+
+	fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+		if matches!(self.0.type_of(), NixType::Null) {
+			visitor.visit_none()
+		} else {
+			visitor.visit_some(self)
+		}
+	}
+
+	fn deserialize_enum<V: de::Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_variants: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		match self.0.type_of() {
+			NixType::String => visitor.visit_enum(self.0.to_string()?.into_deserializer()),
+			NixType::Attrs => visitor.visit_enum(VariantAccess::new(self.0)?),
+			_ => Err(Error::custom(
+				"enums must be represented as a string (unit variant) or a single-field attrset",
+			)),
+		}
+	}
+
+	fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+		self,
+		_name: &'static str,
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		visitor.visit_newtype_struct(self)
+	}
+
+	serde::forward_to_deserialize_any! {
+		bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf
+		unit seq map identifier ignored_any
+	}
+	fn deserialize_unit_struct<V: de::Visitor<'de>>(
+		self,
+		_name: &'static str,
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		self.deserialize_unit(visitor)
+	}
+	fn deserialize_tuple<V: de::Visitor<'de>>(
+		self,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		self.deserialize_seq(visitor)
+	}
+	fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		self.deserialize_seq(visitor)
+	}
+	fn deserialize_struct<V: de::Visitor<'de>>(
+		self,
+		_name: &'static str,
+		_fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		self.deserialize_map(visitor)
+	}
+}
+
+struct AttrsAccess<'v> {
+	attrs: &'v Value,
+	fields: std::vec::IntoIter<String>,
+	current: Option<String>,
+}
+impl<'v> AttrsAccess<'v> {
+	fn new(attrs: &'v Value) -> Result<Self, Error> {
+		Ok(Self {
+			attrs,
+			fields: attrs.list_fields()?.into_iter(),
+			current: None,
+		})
+	}
+}
+impl<'de> de::MapAccess<'de> for AttrsAccess<'_> {
+	type Error = Error;
+
+	fn next_key_seed<K: de::DeserializeSeed<'de>>(
+		&mut self,
+		seed: K,
+	) -> Result<Option<K::Value>, Error> {
+		let Some(name) = self.fields.next() else {
+			return Ok(None);
+		};
+		let key = seed.deserialize(name.clone().into_deserializer())?;
+		self.current = Some(name);
+		Ok(Some(key))
+	}
+	fn next_value_seed<V: de::DeserializeSeed<'de>>(
+		&mut self,
+		seed: V,
+	) -> Result<V::Value, Error> {
+		let name = self
+			.current
+			.take()
+			.expect("next_value_seed called before next_key_seed");
+		let field = self.attrs.get_field(name)?;
+		seed.deserialize(ValueDeserializer(&field))
+	}
+}
+
+struct ListAccess<'v> {
+	list: &'v Value,
+	len: usize,
+	idx: usize,
+}
+impl<'v> ListAccess<'v> {
+	fn new(list: &'v Value) -> Result<Self, Error> {
+		Ok(Self {
+			list,
+			len: list.list_size()?,
+			idx: 0,
+		})
+	}
+}
+impl<'de> de::SeqAccess<'de> for ListAccess<'_> {
+	type Error = Error;
+
+	fn next_element_seed<T: de::DeserializeSeed<'de>>(
+		&mut self,
+		seed: T,
+	) -> Result<Option<T::Value>, Error> {
+		if self.idx >= self.len {
+			return Ok(None);
+		}
+		let elem = self.list.get_elem(self.idx)?;
+		self.idx += 1;
+		seed.deserialize(ValueDeserializer(&elem)).map(Some)
+	}
+	fn size_hint(&self) -> Option<usize> {
+		Some(self.len - self.idx)
+	}
+}
+
+struct VariantAccess {
+	variant: String,
+	value: Value,
+}
+impl VariantAccess {
+	fn new(attrs: &Value) -> Result<Self, Error> {
+		let mut fields = attrs.list_fields()?.into_iter();
+		let variant = fields
+			.next()
+			.ok_or_else(|| Error::custom("expected a single-field attrset for an enum variant"))?;
+		if fields.next().is_some() {
+			return Err(Error::custom(
+				"expected a single-field attrset for an enum variant",
+			));
+		}
+		let value = attrs.get_field(&variant)?;
+		Ok(Self { variant, value })
+	}
+}
+impl<'de> de::EnumAccess<'de> for VariantAccess {
+	type Error = Error;
+	type Variant = Self;
+
+	fn variant_seed<V: de::DeserializeSeed<'de>>(
+		self,
+		seed: V,
+	) -> Result<(V::Value, Self::Variant), Error> {
+		let variant = self.variant.clone();
+		let v = seed.deserialize(variant.into_deserializer())?;
+		Ok((v, self))
+	}
+}
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+	type Error = Error;
+
+	fn unit_variant(self) -> Result<(), Error> {
+		Err(Error::custom("expected unit variant to be a plain string"))
+	}
+	fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+		seed.deserialize(ValueDeserializer(&self.value))
+	}
+	fn tuple_variant<V: de::Visitor<'de>>(
+		self,
+		_len: usize,
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		de::Deserializer::deserialize_seq(ValueDeserializer(&self.value), visitor)
+	}
+	fn struct_variant<V: de::Visitor<'de>>(
+		self,
+		_fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		de::Deserializer::deserialize_map(ValueDeserializer(&self.value), visitor)
+	}
+}
@@ -0,0 +1,206 @@
+//! Proc-macro replacement for the old `nix_expr_inner!` token-muncher.
+//!
+//! `nix_expr_inner!` used to be a `macro_rules!` that could only munch a single level of
+//! tokens, so `Obj { a: [1 2 ({ x })] }` couldn't nest another `Obj { .. }` or list inside
+//! a field value (the field value position matched `$v:expr`, which a bespoke `Obj { .. }`
+//! form doesn't parse as). This crate parses the same surface syntax with a real
+//! recursive-descent parser and desugars to the exact same `Value`/`Field`/`Index`
+//! builder calls the macro used to emit, so nesting is unlimited.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+	Ident, Lit, Token,
+	braced, bracketed, parenthesized,
+	parse::{Parse, ParseStream},
+	parse_macro_input,
+	punctuated::Punctuated,
+};
+
+/// A single field of an `Obj { .. }` literal: either a shorthand `field` (using a
+/// same-named local variable) or `field: <nix expr>`.
+struct ObjField {
+	name: Ident,
+	value: Option<NixExpr>,
+}
+impl Parse for ObjField {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		let name: Ident = input.parse()?;
+		let value = if input.peek(Token![:]) {
+			input.parse::<Token![:]>()?;
+			Some(input.parse()?)
+		} else {
+			None
+		};
+		Ok(Self { name, value })
+	}
+}
+
+/// A single trailer applied to a base identifier: `.field`, `[ expr ]`, or an
+/// applied argument coming from a surrounding `( .. )` application group.
+enum Trailer {
+	Field(Ident),
+	Index(NixExpr),
+	Apply(NixExpr),
+}
+
+enum NixExpr {
+	/// `Obj { a, b: expr, .. }`
+	Obj(Punctuated<ObjField, Token![,]>),
+	/// `[ expr expr .. ]`, space-separated like a Nix list.
+	List(Vec<NixExpr>),
+	/// `{ rust_expr }`, an already-evaluated Rust value to splice in.
+	Interpolated(syn::Expr),
+	/// Any Rust literal, built via `NixExprBuilder::string`.
+	Lit(Lit),
+	/// An identifier (a local variable holding a `Value`), followed by zero or more
+	/// `.field`/`[expr]`/application trailers.
+	Var(Ident, Vec<Trailer>),
+}
+
+impl Parse for NixExpr {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		if input.peek(syn::token::Brace) {
+			let content;
+			braced!(content in input);
+			return Ok(Self::Interpolated(content.parse()?));
+		}
+		if input.peek(syn::token::Bracket) {
+			let content;
+			bracketed!(content in input);
+			let mut items = Vec::new();
+			while !content.is_empty() {
+				items.push(content.parse()?);
+			}
+			return Ok(Self::List(items));
+		}
+		if input.peek(Lit) {
+			return Ok(Self::Lit(input.parse()?));
+		}
+		if input.peek(syn::token::Paren) {
+			// `(base arg1 arg2 ..)`: Nix-style function application, or a plain
+			// `(expr)` grouping when there's only one item inside.
+			let content;
+			parenthesized!(content in input);
+			let first: NixExpr = content.parse()?;
+			if content.is_empty() {
+				return Ok(first);
+			}
+			let Self::Var(ident, mut trailers) = first else {
+				return Err(content.error("only `(ident arg ..)` application form is supported"));
+			};
+			while !content.is_empty() {
+				trailers.push(Trailer::Apply(content.parse()?));
+			}
+			return Ok(Self::Var(ident, trailers));
+		}
+
+		let ident: Ident = input.parse()?;
+		if ident == "Obj" {
+			let content;
+			braced!(content in input);
+			let fields = content.parse_terminated(ObjField::parse, Token![,])?;
+			return Ok(Self::Obj(fields));
+		}
+		let mut trailers = Vec::new();
+		loop {
+			if input.peek(Token![.]) {
+				input.parse::<Token![.]>()?;
+				trailers.push(Trailer::Field(input.parse()?));
+			} else if input.peek(syn::token::Bracket) {
+				let content;
+				bracketed!(content in input);
+				trailers.push(Trailer::Index(content.parse()?));
+			} else {
+				break;
+			}
+		}
+		Ok(Self::Var(ident, trailers))
+	}
+}
+
+fn emit(expr: &NixExpr) -> TokenStream2 {
+	match expr {
+		NixExpr::Obj(fields) => {
+			let inserts = fields.iter().map(|field| {
+				let key = field.name.to_string();
+				let value = match &field.value {
+					Some(v) => emit(v),
+					None => {
+						let name = &field.name;
+						quote!(::nix_eval::Value::from(#name))
+					}
+				};
+				quote! {
+					out.insert(#key, #value);
+				}
+			});
+			quote! {{
+				#[allow(unused_mut)]
+				let mut out = ::std::collections::hash_map::HashMap::new();
+				#(#inserts)*
+				::nix_eval::Value::new_attrs(out)?
+			}}
+		}
+		NixExpr::List(items) => {
+			let items = items.iter().map(emit);
+			quote! {
+				::nix_eval::Value::from(::std::vec![#(#items),*])
+			}
+		}
+		NixExpr::Interpolated(rust_expr) => quote! {
+			::nix_eval::macros::IntoNixExprValue::into_nix_expr_value(&(#rust_expr))?
+		},
+		NixExpr::Lit(lit) => quote! {
+			::nix_eval::macros::NixExprBuilder::string(#lit)
+		},
+		NixExpr::Var(ident, trailers) => {
+			let mut out = quote! {
+				#[allow(unused_mut)]
+				let mut out = #ident.clone();
+			};
+			for trailer in trailers {
+				out = match trailer {
+					Trailer::Field(name) => {
+						let name = name.to_string();
+						quote! {
+							#out
+							out.index_attr(#name);
+						}
+					}
+					Trailer::Index(index_expr) => {
+						let index_expr = emit(index_expr);
+						quote! {
+							#out
+							out.push(::nix_eval::macros::Index::Expr(#index_expr));
+						}
+					}
+					Trailer::Apply(arg_expr) => {
+						let arg_expr = emit(arg_expr);
+						quote! {
+							#out
+							out.push(::nix_eval::macros::Index::ExprApply(#arg_expr));
+						}
+					}
+				};
+			}
+			quote! {{
+				#out
+				out
+			}}
+		}
+	}
+}
+
+/// `nix_expr_inner!(Obj { a: Obj { b: [1 2 ({ x })] }, f: (g x) })`, recursively nested.
+///
+/// Desugars to the same `Value`/`Field`/`Index` builder calls the former
+/// `nix_expr_inner!` macro emitted, with `?`-propagation preserved at every
+/// `Value::new_attrs`/`Value::serialized` call site. `nix_expr!` and `nix_go!` build on
+/// top of this the same way they did before.
+#[proc_macro]
+pub fn nix_expr_inner(input: TokenStream) -> TokenStream {
+	let expr = parse_macro_input!(input as NixExpr);
+	emit(&expr).into()
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+pub mod to_string;
+mod to_value;
+
+pub use to_string::write_nix;
+
+/// A value that can be rendered as Nix source via [`write_nix`].
+///
+/// This mirrors `serde_json::Value`, with the addition of [`Value::Bytes`] (non-UTF-8
+/// string data) and of [`Value::Expr`]/[`Value::Interpolated`]: already-formatted Nix
+/// source that should be spliced in verbatim instead of being escaped as string data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	Null,
+	Boolean(bool),
+	Number(serde_json::Number),
+	String(String),
+	/// Non-UTF-8 string data (e.g. a derivation ATerm env value, or raw
+	/// `std::process::Command` output) that can't be represented as a Rust `String`.
+	/// Rendered as a Nix string literal byte-for-byte rather than through Rust's `String`
+	/// formatting — but only 7-bit bytes other than NUL are representable this way;
+	/// [`write_nix`] errors out on a NUL byte or a byte `0x80..=0xff` rather than silently
+	/// corrupting it.
+	Bytes(Vec<u8>),
+	/// A raw, already-formatted Nix expression (e.g. `config.foo.bar`), emitted verbatim
+	/// rather than escaped as string data.
+	Expr(String),
+	/// A string made of literal text interleaved with raw expressions, rendered as a
+	/// single Nix string with `${...}` antiquotations at the expression positions.
+	Interpolated(Vec<StringPart>),
+	Array(Vec<Value>),
+	Object(BTreeMap<String, Value>),
+}
+
+/// One piece of a [`Value::Interpolated`] string: either literal text (escaped like any
+/// other string content) or a raw Nix expression spliced in as `${...}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+	Literal(String),
+	Expr(String),
+}
+
+impl From<Vec<u8>> for Value {
+	fn from(v: Vec<u8>) -> Self {
+		Value::Bytes(v)
+	}
+}
+impl From<&[u8]> for Value {
+	fn from(v: &[u8]) -> Self {
+		Value::Bytes(v.to_vec())
+	}
+}
+
+impl From<serde_json::Value> for Value {
+	fn from(v: serde_json::Value) -> Self {
+		match v {
+			serde_json::Value::Null => Value::Null,
+			serde_json::Value::Bool(b) => Value::Boolean(b),
+			serde_json::Value::Number(n) => Value::Number(n),
+			serde_json::Value::String(s) => Value::String(s),
+			serde_json::Value::Array(a) => Value::Array(a.into_iter().map(Value::from).collect()),
+			serde_json::Value::Object(o) => {
+				Value::Object(o.into_iter().map(|(k, v)| (k, Value::from(v))).collect())
+			}
+		}
+	}
+}
+
+/// Serialize an arbitrary [`Serialize`] value to a Nix source string, by first
+/// converting it to a [`Value`] and rendering that with [`write_nix`].
+pub fn serialize<T: Serialize>(v: &T) -> Result<String> {
+	let value = to_value::to_value(v)?;
+	write_nix(&value)
+}
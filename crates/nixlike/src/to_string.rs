@@ -1,4 +1,6 @@
-use crate::Value;
+use anyhow::{Result, bail};
+
+use crate::{StringPart, Value};
 
 pub fn write_identifier(k: &str, out: &mut String) {
 	if k.contains(['.', '\'', '\"', '\\', '\n', '\t', '\r', '$']) {
@@ -8,33 +10,81 @@ pub fn write_identifier(k: &str, out: &mut String) {
 	}
 }
 
-fn write_nix_obj_key_buf(k: &str, v: &Value, out: &mut String, padding: &mut usize) {
+fn write_nix_obj_key_buf(k: &str, v: &Value, out: &mut String, padding: &mut usize) -> Result<()> {
 	write_identifier(k, out);
 	match v {
 		Value::Object(o) if o.len() == 1 => {
 			let (k, v) = o.iter().next().unwrap();
 
 			out.push('.');
-			write_nix_obj_key_buf(k, v, out, padding);
+			write_nix_obj_key_buf(k, v, out, padding)?;
 		}
 		v => {
 			out.push_str(" = ");
-			write_nix_buf(v, out, padding);
+			write_nix_buf(v, out, padding)?;
 			out.push(';');
 		}
 	}
+	Ok(())
+}
+
+fn escape_string_body(str: &str) -> String {
+	str.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+		.replace('\t', "\\t")
+		.replace('\r', "\\r")
+		.replace('$', "\\$")
 }
 
 pub fn escape_string(str: &str) -> String {
-	format!(
-		"\"{}\"",
-		str.replace('\\', "\\\\")
-			.replace('"', "\\\"")
-			.replace('\n', "\\n")
-			.replace('\t', "\\t")
-			.replace('\r', "\\r")
-			.replace('$', "\\$")
-	)
+	format!("\"{}\"", escape_string_body(str))
+}
+
+/// Render `bytes` as a Nix string literal, byte by byte: printable ASCII passes through
+/// unchanged, the handful of bytes Nix's string grammar actually defines escapes for
+/// (`"`, `\`, `$`, newline, tab, CR) use those escapes, and any other non-NUL 7-bit byte is pushed
+/// as the literal raw character (valid, since every codepoint below 0x80 is its own
+/// single-byte UTF-8 encoding) — so it survives as that exact byte once Nix parses it back.
+///
+/// Nix's string grammar has no hex/numeric escape (only the ones above), and a lone byte
+/// `0x80..=0xff` isn't representable as a single `char` at all (it isn't valid UTF-8 on its
+/// own, so it can't be written into the `String` we're building here without re-encoding
+/// into multiple bytes, which is exactly the lossy corruption this function exists to
+/// avoid), and a NUL byte can't be held by a Nix string at all. Bailing on those is the honest option until this grows a real side channel (e.g.
+/// writing `bytes` to a file and splicing `builtins.readFile` instead of literal text).
+fn write_nix_bytes(bytes: &[u8], out: &mut String) -> Result<()> {
+	out.push('"');
+	for &b in bytes {
+		match b {
+			b'\\' => out.push_str("\\\\"),
+			b'"' => out.push_str("\\\""),
+			b'$' => out.push_str("\\$"),
+			b'\n' => out.push_str("\\n"),
+			b'\t' => out.push_str("\\t"),
+			b'\r' => out.push_str("\\r"),
+			0x00 => bail!("NUL bytes can't be represented in a Nix string"),
+			0x01..=0x7f => out.push(b as char),
+			_ => bail!("byte {b:#04x} can't be represented as a Nix string literal without lossy re-encoding"),
+		}
+	}
+	out.push('"');
+	Ok(())
+}
+
+fn write_nix_interpolated(parts: &[StringPart], out: &mut String) {
+	out.push('"');
+	for part in parts {
+		match part {
+			StringPart::Literal(s) => out.push_str(&escape_string_body(s)),
+			StringPart::Expr(e) => {
+				out.push_str("${");
+				out.push_str(e);
+				out.push('}');
+			}
+		}
+	}
+	out.push('"');
 }
 
 fn write_padding(out: &mut String, padding: &usize) {
@@ -46,42 +96,68 @@ fn write_padding(out: &mut String, padding: &usize) {
 pub fn write_nix_str_singleline(str: &str, out: &mut String) {
 	out.push_str(&escape_string(str))
 }
-pub fn write_nix_str(str: &str, out: &mut String, padding: &mut usize) {
-	if str.ends_with('\n') {
-		out.push_str("''");
-		*padding += 1;
-		for ele in str[0..str.len() - 1].split('\n') {
-			out.push('\n');
-			write_padding(out, padding);
-			out.push_str(
-				&ele
-					// '' is escaped with '
-					.replace("''", "'''")
-					// ${ is escaped wth ''
-					.replace("${", "''${")
-					// \t is not counted as whitespace for dedent
-					// to avoid confusion, it is printed literally.
-					//
-					// ...Escaped \t literal should be prefixed with '' for... Idk, this logic is complicated.
-					.replace('\t', "''\\t"),
-			);
-		}
+/// Escape the sequences Nix requires inside an indented (`''...''`) string body:
+/// `''` becomes `'''`, `${` becomes `''${`, a tab becomes `''\t`, and a carriage return
+/// becomes `''\r`. A literal backslash needs no change inside `''`.
+fn escape_indented_line(line: &str) -> String {
+	line.replace("''", "'''")
+		.replace("${", "''${")
+		.replace('\t', "''\\t")
+		.replace('\r', "''\\r")
+}
+
+fn write_nix_str_indented(str: &str, out: &mut String, padding: &mut usize) {
+	out.push_str("''");
+	*padding += 1;
+
+	let ends_with_newline = str.ends_with('\n');
+	let body = if ends_with_newline {
+		&str[..str.len() - 1]
+	} else {
+		str
+	};
+	for line in body.split('\n') {
+		out.push('\n');
+		write_padding(out, padding);
+		out.push_str(&escape_indented_line(line));
+	}
+
+	*padding -= 1;
+	if ends_with_newline {
+		// The closing '' goes on its own, dedented line.
 		out.push('\n');
-		*padding -= 1;
 		write_padding(out, padding);
-		// Final newline is assumed due to str.ends_with condition
-		out.push_str("''");
+	}
+	// Otherwise, it directly follows the last line's content.
+	out.push_str("''");
+}
+
+/// Emit `str` as Nix source, matching Nix's own indented-string (`''...''`) dedent
+/// rules: every physical line ends up prefixed by the current `padding`, which the Nix
+/// parser will later strip back off (together with each line's own leading whitespace)
+/// as common indentation, so the *relative* indentation between lines survives the
+/// round trip untouched.
+///
+/// Indented strings are only used for genuinely multiline content; empty strings and
+/// strings containing only whitespace fall back to a single-line `"..."`, since in an
+/// indented string that content would be entirely swallowed as indentation.
+pub fn write_nix_str(str: &str, out: &mut String, padding: &mut usize) {
+	if str.contains('\n') && !str.trim().is_empty() {
+		write_nix_str_indented(str, out, padding);
 	} else {
 		write_nix_str_singleline(str, out);
 	}
 }
 
-fn write_nix_buf(value: &Value, out: &mut String, padding: &mut usize) {
+fn write_nix_buf(value: &Value, out: &mut String, padding: &mut usize) -> Result<()> {
 	match value {
 		Value::Null => out.push_str("null"),
 		Value::Boolean(v) => out.push_str(if *v { "true" } else { "false" }),
 		Value::Number(n) => out.push_str(&format!("{n}")),
 		Value::String(s) => write_nix_str(s, out, padding),
+		Value::Bytes(b) => write_nix_bytes(b, out)?,
+		Value::Expr(e) => out.push_str(e),
+		Value::Interpolated(parts) => write_nix_interpolated(parts, out),
 		Value::Array(a) => {
 			if a.is_empty() {
 				out.push_str("[ ]");
@@ -90,7 +166,7 @@ fn write_nix_buf(value: &Value, out: &mut String, padding: &mut usize) {
 				*padding += 1;
 				for item in a {
 					write_padding(out, padding);
-					write_nix_buf(item, out, padding);
+					write_nix_buf(item, out, padding)?;
 					out.push('\n');
 				}
 				*padding -= 1;
@@ -106,7 +182,7 @@ fn write_nix_buf(value: &Value, out: &mut String, padding: &mut usize) {
 				*padding += 1;
 				for (k, v) in obj {
 					write_padding(out, padding);
-					write_nix_obj_key_buf(k, v, out, padding);
+					write_nix_obj_key_buf(k, v, out, padding)?;
 					out.push('\n');
 				}
 				*padding -= 1;
@@ -115,10 +191,71 @@ fn write_nix_buf(value: &Value, out: &mut String, padding: &mut usize) {
 			}
 		}
 	};
+	Ok(())
+}
+
+pub fn write_nix(value: &Value) -> Result<String> {
+	let mut out = String::new();
+	write_nix_buf(value, &mut out, &mut 0)?;
+	Ok(out)
+}
+
+/// Evaluates `expr` with `nix eval --raw` and returns its stdout, for round-trip tests
+/// that check the bytes Nix actually sees match what we meant to write.
+#[cfg(test)]
+fn nix_eval_raw(expr: &str) -> String {
+	let out = std::process::Command::new("nix")
+		.args(["eval", "--impure", "--raw", "--expr", expr])
+		.output()
+		.expect("failed to run nix eval");
+	assert!(
+		out.status.success(),
+		"nix eval failed: {}",
+		String::from_utf8_lossy(&out.stderr)
+	);
+	String::from_utf8(out.stdout).expect("nix eval --raw produced non-utf8 output")
+}
+
+#[test]
+fn test_indented_string_roundtrip() {
+	for s in [
+		"hello\nworld\n",
+		"hello\nworld",
+		"line one\n  indented\nline three\n",
+		"has a tab\t here\n and a line\n",
+		"has '' quotes\nand ${antiquote} looking text\n",
+		"trailing blank line\n\n",
+	] {
+		let mut out = String::new();
+		write_nix_str(s, &mut out, &mut 0);
+		assert_eq!(nix_eval_raw(&out), s, "roundtrip mismatch for {s:?}, got {out}");
+	}
+}
+
+#[test]
+fn test_empty_and_whitespace_fall_back_to_singleline() {
+	for s in ["", "   ", "\n", "\n\n\t"] {
+		let mut out = String::new();
+		write_nix_str(s, &mut out, &mut 0);
+		assert!(!out.starts_with("''"), "expected singleline form for {s:?}, got {out}");
+		assert_eq!(nix_eval_raw(&out), s);
+	}
+}
+
+#[test]
+fn test_bytes_roundtrip() {
+	for bytes in [b"".as_slice(), b"hello world", &[0x01], &[0x1b], &[b'"', b'\\', b'$', b'\n']] {
+		let mut out = String::new();
+		write_nix_bytes(bytes, &mut out).expect("should be representable");
+		assert_eq!(nix_eval_raw(&out).into_bytes(), bytes, "roundtrip mismatch for {bytes:?}, got {out}");
+	}
 }
 
-pub fn write_nix(value: &Value) -> String {
+#[test]
+fn test_bytes_above_ascii_are_rejected_instead_of_corrupted() {
+	// 0x80 isn't valid UTF-8 on its own, so it can't be written into the `String` we build
+	// without re-encoding into multiple bytes — bail loudly instead of silently mangling it
+	// (the old `\xHH` escape scheme used to do, which Nix doesn't actually understand).
 	let mut out = String::new();
-	write_nix_buf(value, &mut out, &mut 0);
-	out
+	assert!(write_nix_bytes(&[0x80], &mut out).is_err());
 }
@@ -0,0 +1,11 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::Value;
+
+/// Converts any [`Serialize`] type to a [`Value`], by going through `serde_json`'s data
+/// model (which already matches the JSON-equivalent subset of Nix data we need here) and
+/// then remapping into our own enum.
+pub fn to_value<T: Serialize>(v: &T) -> Result<Value> {
+	Ok(Value::from(serde_json::to_value(v)?))
+}
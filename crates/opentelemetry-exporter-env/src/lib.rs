@@ -3,7 +3,8 @@ use std::convert::Infallible;
 use std::env::{self, VarError};
 use std::ffi::OsString;
 use std::num::ParseIntError;
-use std::str::FromStr;
+use std::path::PathBuf;
+use std::str::{FromStr, ParseBoolError};
 use std::time::Duration;
 
 use clap::Parser;
@@ -17,6 +18,8 @@ use opentelemetry_otlp::{
 
 #[cfg(feature = "otlp")]
 mod otlp;
+#[cfg(feature = "otlp")]
+mod retry;
 
 pub enum Error {
 	InvalidUtf8 {
@@ -33,6 +36,11 @@ pub enum Error {
 		value: String,
 		error: ParseIntError,
 	},
+	EnvParseBoolError {
+		env: &'static str,
+		value: String,
+		error: ParseBoolError,
+	},
 }
 impl From<(&'static str, &'static str, String)> for Error {
 	fn from((env, error, value): (&'static str, &'static str, String)) -> Self {
@@ -44,6 +52,11 @@ impl From<(&'static str, ParseIntError, String)> for Error {
 		Self::EnvParseIntError { env, value, error }
 	}
 }
+impl From<(&'static str, ParseBoolError, String)> for Error {
+	fn from((env, error, value): (&'static str, ParseBoolError, String)) -> Self {
+		Self::EnvParseBoolError { env, value, error }
+	}
+}
 impl From<(&'static str, Infallible, String)> for Error {
 	fn from(_v: (&'static str, Infallible, String)) -> Self {
 		unreachable!()
@@ -181,6 +194,36 @@ impl_settings! {
 		/// The timeout value for all outgoing data (traces, metrics, and logs) in milliseconds.
 		#[name("TIMEOUT", "timeout")]
 		timeout: u64,
+		/// Path to a PEM-encoded CA certificate used to verify the collector's certificate, in
+		/// addition to the platform's root certificates for any signal type.
+		#[name("CERTIFICATE", "certificate")]
+		ca_cert: PathBuf,
+		/// Path to a PEM-encoded client certificate presented to the collector for mutual TLS for any signal type.
+		#[name("CLIENT_CERTIFICATE", "client-certificate")]
+		client_cert: PathBuf,
+		/// Path to the PEM-encoded private key matching `client_cert` for any signal type.
+		#[name("CLIENT_KEY", "client-key")]
+		client_key: PathBuf,
+		/// Skips verification of the collector's certificate chain and hostname for any signal type. Only for testing.
+		#[name("INSECURE_SKIP_VERIFY", "insecure-skip-verify")]
+		insecure_skip_verify: bool,
+		/// Maximum number of retries for a batch that fails with a retryable error, before giving up.
+		#[name("RETRY_MAX_RETRIES", "retry-max-retries")]
+		max_retries: u32,
+		/// Backoff before the first retry, in milliseconds; doubles on each subsequent attempt.
+		#[name("RETRY_INITIAL_BACKOFF", "retry-initial-backoff")]
+		initial_backoff_ms: u64,
+		/// Upper bound on the backoff between retries, in milliseconds.
+		#[name("RETRY_MAX_BACKOFF", "retry-max-backoff")]
+		max_backoff_ms: u64,
+		/// Upper bound on the total time spent retrying a single batch, in milliseconds, after
+		/// which the last error is returned even if `max_retries` hasn't been reached.
+		#[name("RETRY_MAX_ELAPSED", "retry-max-elapsed")]
+		max_elapsed_ms: u64,
+		/// Maximum number of export requests allowed in flight at once, for any signal type.
+		/// Defaults to 1, i.e. exports are serialized as before this setting existed.
+		#[name("MAX_CONCURRENT_EXPORTS", "max-concurrent-exports")]
+		max_concurrent_exports: u32,
 	}
 }
 impl_settings! {
@@ -203,6 +246,19 @@ impl_settings! {
 		/// The timeout value for all outgoing logs in milliseconds.
 		#[name("TIMEOUT", "timeout")]
 		timeout: u64,
+		/// Path to a PEM-encoded CA certificate used to verify the collector's certificate, in
+		/// addition to the platform's root certificates for log data only.
+		#[name("CERTIFICATE", "certificate")]
+		ca_cert: PathBuf,
+		/// Path to a PEM-encoded client certificate presented to the collector for mutual TLS for log data only.
+		#[name("CLIENT_CERTIFICATE", "client-certificate")]
+		client_cert: PathBuf,
+		/// Path to the PEM-encoded private key matching `client_cert` for log data only.
+		#[name("CLIENT_KEY", "client-key")]
+		client_key: PathBuf,
+		/// Skips verification of the collector's certificate chain and hostname for log data only. Only for testing.
+		#[name("INSECURE_SKIP_VERIFY", "insecure-skip-verify")]
+		insecure_skip_verify: bool,
 	}
 }
 impl_settings! {
@@ -225,6 +281,19 @@ impl_settings! {
 		/// The timeout value for all outgoing metrics in milliseconds.
 		#[name("TIMEOUT", "timeout")]
 		timeout: u64,
+		/// Path to a PEM-encoded CA certificate used to verify the collector's certificate, in
+		/// addition to the platform's root certificates for metric data only.
+		#[name("CERTIFICATE", "certificate")]
+		ca_cert: PathBuf,
+		/// Path to a PEM-encoded client certificate presented to the collector for mutual TLS for metric data only.
+		#[name("CLIENT_CERTIFICATE", "client-certificate")]
+		client_cert: PathBuf,
+		/// Path to the PEM-encoded private key matching `client_cert` for metric data only.
+		#[name("CLIENT_KEY", "client-key")]
+		client_key: PathBuf,
+		/// Skips verification of the collector's certificate chain and hostname for metric data only. Only for testing.
+		#[name("INSECURE_SKIP_VERIFY", "insecure-skip-verify")]
+		insecure_skip_verify: bool,
 	}
 }
 
@@ -248,6 +317,19 @@ impl_settings! {
 		/// The timeout value for all outgoing traces in milliseconds.
 		#[name("TIMEOUT", "timeout")]
 		timeout: u64,
+		/// Path to a PEM-encoded CA certificate used to verify the collector's certificate, in
+		/// addition to the platform's root certificates for trace data only.
+		#[name("CERTIFICATE", "certificate")]
+		ca_cert: PathBuf,
+		/// Path to a PEM-encoded client certificate presented to the collector for mutual TLS for trace data only.
+		#[name("CLIENT_CERTIFICATE", "client-certificate")]
+		client_cert: PathBuf,
+		/// Path to the PEM-encoded private key matching `client_cert` for trace data only.
+		#[name("CLIENT_KEY", "client-key")]
+		client_key: PathBuf,
+		/// Skips verification of the collector's certificate chain and hostname for trace data only. Only for testing.
+		#[name("INSECURE_SKIP_VERIFY", "insecure-skip-verify")]
+		insecure_skip_verify: bool,
 	}
 }
 
@@ -257,6 +339,16 @@ enum ProviderError {
 	UnsetProtocol,
 	#[error("endpoint is not set")]
 	EndpointUnset,
+	#[error("invalid header {raw:?}: {reason}")]
+	InvalidHeader { raw: String, reason: &'static str },
+	#[error("TLS configuration error: {0}")]
+	Tls(String),
+	#[error("invalid value {value:?} for {env}: {reason}")]
+	InvalidEnvValue {
+		env: &'static str,
+		value: String,
+		reason: &'static str,
+	},
 	#[cfg(feature = "otlp")]
 	#[error("failed to build exporter: {0}")]
 	Exporter(#[from] ExporterBuildError),
@@ -1,61 +1,248 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 
 use opentelemetry_otlp::tonic_types::metadata::MetadataMap;
+use opentelemetry_otlp::tonic_types::transport::{Certificate, ClientTlsConfig, Identity};
 use opentelemetry_otlp::{
 	LogExporter, MetricExporter, SpanExporter, WithExportConfig as _, WithHttpConfig as _,
 	WithTonicConfig as _,
 };
 
+use tracing::warn;
+
+use crate::retry::{RetryPolicy, RetryingLogExporter, RetryingMetricExporter, RetryingSpanExporter};
 use crate::{
-	OtlpBaseSettings, OtlpLogsSettings, OtlpMetricsSettings, OtlpProtocol, ProviderError,
-	ProviderResult,
+	Compression, OtlpBaseSettings, OtlpLogsSettings, OtlpMetricsSettings, OtlpProtocol,
+	ProviderError, ProviderResult,
 };
 
-fn parse_headers<'a>(
-	headers: &'a str,
-) -> std::iter::Map<std::str::Split<'a, char>, impl FnMut(&'a str) -> (&'a str, &'a str)> {
-	headers.split(',').map(|header| {
-		let mut parts = header.splitn(2, '=');
-		let key = parts.next().unwrap();
-		let value = parts.next().unwrap_or("");
-		(key, value)
-	})
+fn read_pem(path: &PathBuf) -> ProviderResult<Vec<u8>> {
+	std::fs::read(path)
+		.map_err(|err| ProviderError::Tls(format!("failed to read {path:?}: {err}")))
+}
+
+/// Resolved, per-signal TLS settings: a `base`/per-signal pair merged the same way as every
+/// other field in [`logger_exporter`]/[`metric_exporter`]/[`span_exporter`] (signal overrides
+/// base when set).
+struct ResolvedTls<'a> {
+	ca_cert: Option<&'a PathBuf>,
+	client_cert: Option<&'a PathBuf>,
+	client_key: Option<&'a PathBuf>,
+	insecure_skip_verify: bool,
 }
 
-fn parse_headers_metadata_map(headers: Option<&str>) -> MetadataMap {
+/// Builds the `tonic` TLS config for the gRPC exporters, or `None` if nothing was configured
+/// (letting `tonic` fall back to the platform's default roots).
+fn tonic_tls_config(tls: &ResolvedTls) -> ProviderResult<Option<ClientTlsConfig>> {
+	// `tonic`'s `ClientTlsConfig` has no "skip verification" knob at all, unlike the `reqwest`
+	// client `http_tls_client` builds for the HTTP exporters - there's no option here that
+	// would make this setting do anything, so refuse it outright instead of silently building
+	// the same verifying config as `insecure_skip_verify: false` and leaving the user to
+	// discover that the hard way.
+	if tls.insecure_skip_verify {
+		return Err(ProviderError::Tls(
+			"insecure_skip_verify is not supported for the gRPC OTLP protocol (tonic has no \
+			 certificate-verification override); use protocol = \"http/protobuf\" or \
+			 \"http/json\" instead, or configure ca_cert if the goal is a custom trust root"
+				.to_owned(),
+		));
+	}
+	if tls.ca_cert.is_none() && tls.client_cert.is_none() {
+		return Ok(None);
+	}
+	let mut config = ClientTlsConfig::new();
+	if let Some(ca_cert) = tls.ca_cert {
+		config = config.ca_certificate(Certificate::from_pem(read_pem(ca_cert)?));
+	}
+	if let (Some(cert), Some(key)) = (tls.client_cert, tls.client_key) {
+		config = config.identity(Identity::from_pem(read_pem(cert)?, read_pem(key)?));
+	}
+	Ok(Some(config))
+}
+
+/// Builds a custom `reqwest` client carrying the same CA/identity for the HTTP exporters, or
+/// `None` to use the default client when nothing was configured.
+fn http_tls_client(tls: &ResolvedTls) -> ProviderResult<Option<reqwest::Client>> {
+	if tls.ca_cert.is_none() && tls.client_cert.is_none() && !tls.insecure_skip_verify {
+		return Ok(None);
+	}
+	let mut builder = reqwest::Client::builder().danger_accept_invalid_certs(tls.insecure_skip_verify);
+	if let Some(ca_cert) = tls.ca_cert {
+		let cert = reqwest::Certificate::from_pem(&read_pem(ca_cert)?)
+			.map_err(|err| ProviderError::Tls(format!("invalid CA certificate: {err}")))?;
+		builder = builder.add_root_certificate(cert);
+	}
+	if let (Some(cert), Some(key)) = (tls.client_cert, tls.client_key) {
+		let mut pem = read_pem(cert)?;
+		pem.extend(read_pem(key)?);
+		let identity = reqwest::Identity::from_pem(&pem)
+			.map_err(|err| ProviderError::Tls(format!("invalid client certificate/key: {err}")))?;
+		builder = builder.identity(identity);
+	}
+	Ok(Some(builder.build().map_err(|err| {
+		ProviderError::Tls(format!("failed to build TLS HTTP client: {err}"))
+	})?))
+}
+
+/// A gRPC metadata key is a valid HTTP/2 header name: lowercase ASCII letters, digits, `-`,
+/// `_` and `.` (optionally ending in `-bin` for binary values, which we don't otherwise support
+/// here since `OTEL_EXPORTER_OTLP_HEADERS` only carries plain strings).
+fn is_valid_metadata_key(key: &str) -> bool {
+	!key.is_empty()
+		&& key
+			.bytes()
+			.all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+/// Decodes `%XX` percent-escapes, as used by `OTEL_EXPORTER_OTLP_HEADERS` to carry header
+/// values containing `,` or `=` (e.g. `authorization=Bearer%20abc`).
+fn percent_decode(s: &str) -> Result<String, &'static str> {
+	let bytes = s.as_bytes();
+	let mut out = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%' {
+			let hex = s
+				.get(i + 1..i + 3)
+				.ok_or("truncated percent-escape in header value")?;
+			let byte = u8::from_str_radix(hex, 16).map_err(|_| "invalid percent-escape in header value")?;
+			out.push(byte);
+			i += 3;
+		} else {
+			out.push(bytes[i]);
+			i += 1;
+		}
+	}
+	String::from_utf8(out).map_err(|_| "percent-decoded header value is not valid UTF-8")
+}
+
+/// Parses an `OTEL_EXPORTER_OTLP_*HEADERS`-style `key1=value1,key2=value2` list: segments are
+/// trimmed and empty ones skipped, keys are validated as gRPC metadata keys, and values are
+/// percent-decoded. Never panics on malformed input, unlike a bare `split`/`unwrap`.
+fn parse_headers(headers: &str) -> ProviderResult<Vec<(String, String)>> {
 	headers
-		.map(|headers| {
-			MetadataMap::from_headers(
-				parse_headers(headers)
-					.map(|(key, value)| (key.parse().unwrap(), value.parse().unwrap()))
-					.collect(),
-			)
+		.split(',')
+		.map(str::trim)
+		.filter(|segment| !segment.is_empty())
+		.map(|segment| {
+			let mut parts = segment.splitn(2, '=');
+			let key = parts.next().unwrap_or("").trim();
+			let value = parts.next().unwrap_or("").trim();
+			if !is_valid_metadata_key(key) {
+				return Err(ProviderError::InvalidHeader {
+					raw: segment.to_owned(),
+					reason: "key is not a valid gRPC metadata key",
+				});
+			}
+			let value = percent_decode(value).map_err(|reason| ProviderError::InvalidHeader {
+				raw: segment.to_owned(),
+				reason,
+			})?;
+			Ok((key.to_owned(), value))
 		})
-		.unwrap_or_default()
+		.collect()
 }
-fn parse_headers_hashmap(headers: Option<&str>) -> HashMap<String, String> {
-	headers
-		.map(|headers| {
-			parse_headers(headers)
-				.map(|(key, value)| (key.into(), value.into()))
-				.collect()
+
+fn parse_headers_metadata_map(headers: Option<&str>) -> ProviderResult<MetadataMap> {
+	let Some(headers) = headers else {
+		return Ok(MetadataMap::default());
+	};
+	let mut map = MetadataMap::new();
+	for (key, value) in parse_headers(headers)? {
+		let meta_key = key.parse().map_err(|_| ProviderError::InvalidHeader {
+			raw: key.clone(),
+			reason: "key is not a valid gRPC metadata key",
+		})?;
+		let meta_value = value.parse().map_err(|_| ProviderError::InvalidHeader {
+			raw: value,
+			reason: "value is not a valid gRPC metadata value",
+		})?;
+		map.insert(meta_key, meta_value);
+	}
+	Ok(map)
+}
+fn parse_headers_hashmap(headers: Option<&str>) -> ProviderResult<HashMap<String, String>> {
+	Ok(match headers {
+		Some(headers) => parse_headers(headers)?.into_iter().collect(),
+		None => HashMap::new(),
+	})
+}
+
+/// Reads a raw `OTEL_EXPORTER_OTLP_*` string env var, for use as a fallback when neither the
+/// signal-specific nor base config set a value. Missing or non-UTF-8 is treated as "not set",
+/// same as every other optional source here; only a value that's present but fails to parse is
+/// an error (see [`parse_env`]).
+fn read_env(name: &'static str) -> Option<String> {
+	std::env::var(name).ok()
+}
+
+/// Like [`read_env`], but parses the value via `FromStr`, surfacing a malformed (present but
+/// unparseable) value as an error instead of silently falling through to the next tier.
+fn parse_env<T: FromStr>(name: &'static str, reason: &'static str) -> ProviderResult<Option<T>> {
+	read_env(name)
+		.map(|value| {
+			T::from_str(&value).map_err(|_| ProviderError::InvalidEnvValue { env: name, value, reason })
 		})
-		.unwrap_or_default()
+		.transpose()
 }
 
-fn logger_exporter(base: &OtlpBaseSettings, log: &OtlpLogsSettings) -> ProviderResult<LogExporter> {
+/// Like [`read_env`], but validates the value with the same hardened parser used for the
+/// `headers` config field, so a malformed `OTEL_EXPORTER_OTLP_*HEADERS` env var is caught here
+/// rather than failing confusingly later when the exporter is actually built.
+fn read_env_headers(name: &'static str) -> ProviderResult<Option<String>> {
+	let Some(raw) = read_env(name) else {
+		return Ok(None);
+	};
+	parse_headers(&raw)?;
+	Ok(Some(raw))
+}
+
+fn logger_exporter(
+	base: &OtlpBaseSettings,
+	log: &OtlpLogsSettings,
+) -> ProviderResult<RetryingLogExporter> {
+	let retry_policy = RetryPolicy::from_settings(base);
+	let max_concurrent_exports = base.max_concurrent_exports.unwrap_or(1);
+	// Precedence, per signal field: log config > signal-specific `OTEL_EXPORTER_OTLP_LOGS_*`
+	// env > base config > base `OTEL_EXPORTER_OTLP_*` env > built-in default.
+	let base_endpoint = base.endpoint.clone().or_else(|| read_env("OTEL_EXPORTER_OTLP_ENDPOINT"));
 	let endpoint = log
 		.endpoint
 		.clone()
-		.or_else(|| Some(format!("{}/v1/logs", base.endpoint.as_ref()?)))
+		.or_else(|| read_env("OTEL_EXPORTER_OTLP_LOGS_ENDPOINT"))
+		.or_else(|| Some(format!("{}/v1/logs", base_endpoint.as_ref()?)))
 		.ok_or(ProviderError::EndpointUnset)?;
-	let headers = log.headers.as_deref().or(base.headers.as_deref());
-	let timeout = Duration::from_millis(log.timeout.or(base.timeout).unwrap_or(10000));
+	let headers = log
+		.headers
+		.clone()
+		.or(read_env_headers("OTEL_EXPORTER_OTLP_LOGS_HEADERS")?)
+		.or(base.headers.clone())
+		.or(read_env_headers("OTEL_EXPORTER_OTLP_HEADERS")?);
+	let headers = headers.as_deref();
+	let timeout = Duration::from_millis(
+		log.timeout
+			.or(parse_env("OTEL_EXPORTER_OTLP_LOGS_TIMEOUT", "not an integer number of milliseconds")?)
+			.or(base.timeout)
+			.or(parse_env("OTEL_EXPORTER_OTLP_TIMEOUT", "not an integer number of milliseconds")?)
+			.unwrap_or(10000),
+	);
+	let tls = ResolvedTls {
+		ca_cert: log.ca_cert.as_ref().or(base.ca_cert.as_ref()),
+		client_cert: log.client_cert.as_ref().or(base.client_cert.as_ref()),
+		client_key: log.client_key.as_ref().or(base.client_key.as_ref()),
+		insecure_skip_verify: log
+			.insecure_skip_verify
+			.or(base.insecure_skip_verify)
+			.unwrap_or(false),
+	};
 
 	let protocol = log
 		.protocol
+		.or(parse_env("OTEL_EXPORTER_OTLP_LOGS_PROTOCOL", "not one of grpc, http/protobuf, http/json")?)
 		.or(base.protocol)
+		.or(parse_env("OTEL_EXPORTER_OTLP_PROTOCOL", "not one of grpc, http/protobuf, http/json")?)
 		.ok_or(ProviderError::UnsetProtocol)?;
 
 	match protocol {
@@ -63,43 +250,102 @@ fn logger_exporter(base: &OtlpBaseSettings, log: &OtlpLogsSettings) -> ProviderR
 			let mut builder = LogExporter::builder()
 				.with_tonic()
 				.with_endpoint(endpoint)
-				.with_metadata(parse_headers_metadata_map(headers))
+				.with_metadata(parse_headers_metadata_map(headers)?)
 				.with_protocol(protocol.into())
 				.with_timeout(timeout);
-			let compression = log.compression.or(base.compression);
+			if let Some(tls_config) = tonic_tls_config(&tls)? {
+				builder = builder.with_tls_config(tls_config);
+			}
+			let compression = log
+				.compression
+				.or(parse_env("OTEL_EXPORTER_OTLP_LOGS_COMPRESSION", "not one of gzip, zstd")?)
+				.or(base.compression)
+				.or(parse_env("OTEL_EXPORTER_OTLP_COMPRESSION", "not one of gzip, zstd")?);
 			if let Some(compression) = compression {
 				builder = builder.with_compression(compression.into());
 			}
 
-			Ok(builder.build()?)
+			Ok(RetryingLogExporter::new(builder.build()?, retry_policy, max_concurrent_exports))
 		}
 		OtlpProtocol::HttpProtobuf | OtlpProtocol::HttpJson => {
-			let builder = LogExporter::builder()
+			let mut builder = LogExporter::builder()
 				.with_http()
 				.with_endpoint(endpoint)
-				.with_headers(parse_headers_hashmap(headers))
+				.with_headers(parse_headers_hashmap(headers)?)
 				.with_protocol(protocol.into())
 				.with_timeout(timeout);
+			if let Some(client) = http_tls_client(&tls)? {
+				builder = builder.with_http_client(client);
+			}
+			let compression = log
+				.compression
+				.or(parse_env("OTEL_EXPORTER_OTLP_LOGS_COMPRESSION", "not one of gzip, zstd")?)
+				.or(base.compression)
+				.or(parse_env("OTEL_EXPORTER_OTLP_COMPRESSION", "not one of gzip, zstd")?);
+			match compression {
+				Some(Compression::Gzip) => builder = builder.with_compression(Compression::Gzip.into()),
+				Some(Compression::Zstd) => warn!(
+					"zstd compression was requested for the logs OTLP exporter, but only gzip is \
+					 supported over HTTP; sending uncompressed data"
+				),
+				None => {}
+			}
 
-			Ok(builder.build()?)
+			Ok(RetryingLogExporter::new(builder.build()?, retry_policy, max_concurrent_exports))
 		}
 	}
 }
 fn metric_exporter(
 	base: &OtlpBaseSettings,
 	metric: &OtlpMetricsSettings,
-) -> ProviderResult<MetricExporter> {
+) -> ProviderResult<RetryingMetricExporter> {
+	let retry_policy = RetryPolicy::from_settings(base);
+	let max_concurrent_exports = base.max_concurrent_exports.unwrap_or(1);
+	// Precedence, per signal field: metric config > signal-specific `OTEL_EXPORTER_OTLP_METRICS_*`
+	// env > base config > base `OTEL_EXPORTER_OTLP_*` env > built-in default.
+	let base_endpoint = base.endpoint.clone().or_else(|| read_env("OTEL_EXPORTER_OTLP_ENDPOINT"));
 	let endpoint = metric
 		.endpoint
 		.clone()
-		.or_else(|| Some(format!("{}/v1/metrics", base.endpoint.as_ref()?)))
+		.or_else(|| read_env("OTEL_EXPORTER_OTLP_METRICS_ENDPOINT"))
+		.or_else(|| Some(format!("{}/v1/metrics", base_endpoint.as_ref()?)))
 		.ok_or(ProviderError::EndpointUnset)?;
-	let headers = metric.headers.as_deref().or(base.headers.as_deref());
-	let timeout = Duration::from_millis(metric.timeout.or(base.timeout).unwrap_or(10000));
+	let headers = metric
+		.headers
+		.clone()
+		.or(read_env_headers("OTEL_EXPORTER_OTLP_METRICS_HEADERS")?)
+		.or(base.headers.clone())
+		.or(read_env_headers("OTEL_EXPORTER_OTLP_HEADERS")?);
+	let headers = headers.as_deref();
+	let timeout = Duration::from_millis(
+		metric
+			.timeout
+			.or(parse_env(
+				"OTEL_EXPORTER_OTLP_METRICS_TIMEOUT",
+				"not an integer number of milliseconds",
+			)?)
+			.or(base.timeout)
+			.or(parse_env("OTEL_EXPORTER_OTLP_TIMEOUT", "not an integer number of milliseconds")?)
+			.unwrap_or(10000),
+	);
+	let tls = ResolvedTls {
+		ca_cert: metric.ca_cert.as_ref().or(base.ca_cert.as_ref()),
+		client_cert: metric.client_cert.as_ref().or(base.client_cert.as_ref()),
+		client_key: metric.client_key.as_ref().or(base.client_key.as_ref()),
+		insecure_skip_verify: metric
+			.insecure_skip_verify
+			.or(base.insecure_skip_verify)
+			.unwrap_or(false),
+	};
 
 	let protocol = metric
 		.protocol
+		.or(parse_env(
+			"OTEL_EXPORTER_OTLP_METRICS_PROTOCOL",
+			"not one of grpc, http/protobuf, http/json",
+		)?)
 		.or(base.protocol)
+		.or(parse_env("OTEL_EXPORTER_OTLP_PROTOCOL", "not one of grpc, http/protobuf, http/json")?)
 		.ok_or(ProviderError::UnsetProtocol)?;
 
 	match protocol {
@@ -107,43 +353,102 @@ fn metric_exporter(
 			let mut builder = MetricExporter::builder()
 				.with_tonic()
 				.with_endpoint(endpoint)
-				.with_metadata(parse_headers_metadata_map(headers))
+				.with_metadata(parse_headers_metadata_map(headers)?)
 				.with_protocol(protocol.into())
 				.with_timeout(timeout);
-			let compression = metric.compression.or(base.compression);
+			if let Some(tls_config) = tonic_tls_config(&tls)? {
+				builder = builder.with_tls_config(tls_config);
+			}
+			let compression = metric
+				.compression
+				.or(parse_env("OTEL_EXPORTER_OTLP_METRICS_COMPRESSION", "not one of gzip, zstd")?)
+				.or(base.compression)
+				.or(parse_env("OTEL_EXPORTER_OTLP_COMPRESSION", "not one of gzip, zstd")?);
 			if let Some(compression) = compression {
 				builder = builder.with_compression(compression.into());
 			}
 
-			Ok(builder.build()?)
+			Ok(RetryingMetricExporter::new(builder.build()?, retry_policy, max_concurrent_exports))
 		}
 		OtlpProtocol::HttpProtobuf | OtlpProtocol::HttpJson => {
-			let builder = MetricExporter::builder()
+			let mut builder = MetricExporter::builder()
 				.with_http()
 				.with_endpoint(endpoint)
-				.with_headers(parse_headers_hashmap(headers))
+				.with_headers(parse_headers_hashmap(headers)?)
 				.with_protocol(protocol.into())
 				.with_timeout(timeout);
+			if let Some(client) = http_tls_client(&tls)? {
+				builder = builder.with_http_client(client);
+			}
+			let compression = metric
+				.compression
+				.or(parse_env("OTEL_EXPORTER_OTLP_METRICS_COMPRESSION", "not one of gzip, zstd")?)
+				.or(base.compression)
+				.or(parse_env("OTEL_EXPORTER_OTLP_COMPRESSION", "not one of gzip, zstd")?);
+			match compression {
+				Some(Compression::Gzip) => builder = builder.with_compression(Compression::Gzip.into()),
+				Some(Compression::Zstd) => warn!(
+					"zstd compression was requested for the metrics OTLP exporter, but only gzip \
+					 is supported over HTTP; sending uncompressed data"
+				),
+				None => {}
+			}
 
-			Ok(builder.build()?)
+			Ok(RetryingMetricExporter::new(builder.build()?, retry_policy, max_concurrent_exports))
 		}
 	}
 }
 fn span_exporter(
 	base: &OtlpBaseSettings,
 	trace: &OtlpMetricsSettings,
-) -> ProviderResult<SpanExporter> {
+) -> ProviderResult<RetryingSpanExporter> {
+	let retry_policy = RetryPolicy::from_settings(base);
+	let max_concurrent_exports = base.max_concurrent_exports.unwrap_or(1);
+	// Precedence, per signal field: trace config > signal-specific `OTEL_EXPORTER_OTLP_TRACES_*`
+	// env > base config > base `OTEL_EXPORTER_OTLP_*` env > built-in default.
+	let base_endpoint = base.endpoint.clone().or_else(|| read_env("OTEL_EXPORTER_OTLP_ENDPOINT"));
 	let endpoint = trace
 		.endpoint
 		.clone()
-		.or_else(|| Some(format!("{}/v1/traces", base.endpoint.as_ref()?)))
+		.or_else(|| read_env("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"))
+		.or_else(|| Some(format!("{}/v1/traces", base_endpoint.as_ref()?)))
 		.ok_or(ProviderError::EndpointUnset)?;
-	let headers = trace.headers.as_deref().or(base.headers.as_deref());
-	let timeout = Duration::from_millis(trace.timeout.or(base.timeout).unwrap_or(10000));
+	let headers = trace
+		.headers
+		.clone()
+		.or(read_env_headers("OTEL_EXPORTER_OTLP_TRACES_HEADERS")?)
+		.or(base.headers.clone())
+		.or(read_env_headers("OTEL_EXPORTER_OTLP_HEADERS")?);
+	let headers = headers.as_deref();
+	let timeout = Duration::from_millis(
+		trace
+			.timeout
+			.or(parse_env(
+				"OTEL_EXPORTER_OTLP_TRACES_TIMEOUT",
+				"not an integer number of milliseconds",
+			)?)
+			.or(base.timeout)
+			.or(parse_env("OTEL_EXPORTER_OTLP_TIMEOUT", "not an integer number of milliseconds")?)
+			.unwrap_or(10000),
+	);
+	let tls = ResolvedTls {
+		ca_cert: trace.ca_cert.as_ref().or(base.ca_cert.as_ref()),
+		client_cert: trace.client_cert.as_ref().or(base.client_cert.as_ref()),
+		client_key: trace.client_key.as_ref().or(base.client_key.as_ref()),
+		insecure_skip_verify: trace
+			.insecure_skip_verify
+			.or(base.insecure_skip_verify)
+			.unwrap_or(false),
+	};
 
 	let protocol = trace
 		.protocol
+		.or(parse_env(
+			"OTEL_EXPORTER_OTLP_TRACES_PROTOCOL",
+			"not one of grpc, http/protobuf, http/json",
+		)?)
 		.or(base.protocol)
+		.or(parse_env("OTEL_EXPORTER_OTLP_PROTOCOL", "not one of grpc, http/protobuf, http/json")?)
 		.ok_or(ProviderError::UnsetProtocol)?;
 
 	match protocol {
@@ -151,25 +456,48 @@ fn span_exporter(
 			let mut builder = SpanExporter::builder()
 				.with_tonic()
 				.with_endpoint(endpoint)
-				.with_metadata(parse_headers_metadata_map(headers))
+				.with_metadata(parse_headers_metadata_map(headers)?)
 				.with_protocol(protocol.into())
 				.with_timeout(timeout);
-			let compression = trace.compression.or(base.compression);
+			if let Some(tls_config) = tonic_tls_config(&tls)? {
+				builder = builder.with_tls_config(tls_config);
+			}
+			let compression = trace
+				.compression
+				.or(parse_env("OTEL_EXPORTER_OTLP_TRACES_COMPRESSION", "not one of gzip, zstd")?)
+				.or(base.compression)
+				.or(parse_env("OTEL_EXPORTER_OTLP_COMPRESSION", "not one of gzip, zstd")?);
 			if let Some(compression) = compression {
 				builder = builder.with_compression(compression.into());
 			}
 
-			Ok(builder.build()?)
+			Ok(RetryingSpanExporter::new(builder.build()?, retry_policy, max_concurrent_exports))
 		}
 		OtlpProtocol::HttpProtobuf | OtlpProtocol::HttpJson => {
-			let builder = SpanExporter::builder()
+			let mut builder = SpanExporter::builder()
 				.with_http()
 				.with_endpoint(endpoint)
-				.with_headers(parse_headers_hashmap(headers))
+				.with_headers(parse_headers_hashmap(headers)?)
 				.with_protocol(protocol.into())
 				.with_timeout(timeout);
+			if let Some(client) = http_tls_client(&tls)? {
+				builder = builder.with_http_client(client);
+			}
+			let compression = trace
+				.compression
+				.or(parse_env("OTEL_EXPORTER_OTLP_TRACES_COMPRESSION", "not one of gzip, zstd")?)
+				.or(base.compression)
+				.or(parse_env("OTEL_EXPORTER_OTLP_COMPRESSION", "not one of gzip, zstd")?);
+			match compression {
+				Some(Compression::Gzip) => builder = builder.with_compression(Compression::Gzip.into()),
+				Some(Compression::Zstd) => warn!(
+					"zstd compression was requested for the traces OTLP exporter, but only gzip \
+					 is supported over HTTP; sending uncompressed data"
+				),
+				None => {}
+			}
 
-			Ok(builder.build()?)
+			Ok(RetryingSpanExporter::new(builder.build()?, retry_policy, max_concurrent_exports))
 		}
 	}
 }
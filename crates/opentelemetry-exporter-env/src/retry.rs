@@ -0,0 +1,312 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use opentelemetry_otlp::{LogExporter, MetricExporter, SpanExporter};
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::logs::{LogBatch, LogExporter as LogExporterTrait};
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+use opentelemetry_sdk::trace::{SpanData, SpanExporter as SpanExporterTrait};
+use opentelemetry_sdk::Resource;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::warn;
+
+use crate::OtlpBaseSettings;
+
+/// Backoff/retry policy for a single exported batch, read once from [`OtlpBaseSettings`] at
+/// provider setup time (see [`RetryingLogExporter`]/[`RetryingMetricExporter`]/
+/// [`RetryingSpanExporter`]).
+#[derive(Clone, Copy)]
+pub(crate) struct RetryPolicy {
+	max_retries: u32,
+	initial_backoff: Duration,
+	max_backoff: Duration,
+	max_elapsed: Duration,
+}
+impl RetryPolicy {
+	pub(crate) fn from_settings(base: &OtlpBaseSettings) -> Self {
+		Self {
+			max_retries: base.max_retries.unwrap_or(5),
+			initial_backoff: Duration::from_millis(base.initial_backoff_ms.unwrap_or(100)),
+			max_backoff: Duration::from_millis(base.max_backoff_ms.unwrap_or(10_000)),
+			max_elapsed: Duration::from_millis(base.max_elapsed_ms.unwrap_or(60_000)),
+		}
+	}
+
+	/// `min(max_backoff, initial_backoff * 2^attempt)` plus uniform jitter in `[0, backoff/2)`.
+	fn backoff_for(&self, attempt: u32) -> Duration {
+		let exp = self
+			.initial_backoff
+			.saturating_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX));
+		let backoff = exp.min(self.max_backoff);
+		backoff.saturating_add(jitter(backoff / 2))
+	}
+}
+
+/// Bounds how many export requests may be in flight at once, sized from
+/// `max_concurrent_exports` (default 1, preserving the historical one-at-a-time behavior).
+/// [`RetryingLogExporter`]/[`RetryingMetricExporter`] use [`Self::gated`] directly, since their
+/// `export` already takes `&self` and so several calls can simply run concurrently under the
+/// same permit pool; [`RetryingSpanExporter`] uses [`Self::spawn`] instead, since its `&mut self`
+/// signature would otherwise serialize every batch behind this wrapper regardless of the permit
+/// count.
+pub(crate) struct ConcurrencyLimiter {
+	semaphore: Arc<Semaphore>,
+	in_flight: tokio::sync::Mutex<JoinSet<()>>,
+}
+
+impl ConcurrencyLimiter {
+	pub(crate) fn new(max_concurrent_exports: u32) -> Self {
+		Self {
+			semaphore: Arc::new(Semaphore::new(max_concurrent_exports.max(1) as usize)),
+			in_flight: tokio::sync::Mutex::new(JoinSet::new()),
+		}
+	}
+
+	/// Acquires a permit and runs `fut` while holding it, bounding the number of concurrent
+	/// callers to `max_concurrent_exports`; the permit is released when `fut` completes.
+	pub(crate) async fn gated<T>(&self, fut: impl Future<Output = T>) -> T {
+		let _permit = self.semaphore.acquire().await;
+		fut.await
+	}
+
+	/// Acquires a permit, then spawns `task` as an independent task so the caller can return
+	/// immediately instead of serializing subsequent exports behind it; the permit is released
+	/// when `task` completes. Tracked in `in_flight` so [`Self::drain`] can wait for it.
+	pub(crate) async fn spawn<F>(&self, name: &'static str, task: F)
+	where
+		F: Future<Output = OTelSdkResult> + Send + 'static,
+	{
+		let Ok(permit) = self.semaphore.clone().acquire_owned().await else {
+			return;
+		};
+		self.in_flight.lock().await.spawn(async move {
+			let _permit = permit;
+			if let Err(err) = task.await {
+				warn!("{name} export failed after exhausting retries: {err}");
+			}
+		});
+	}
+
+	/// Awaits every task spawned via [`Self::spawn`] that hasn't completed yet, so `shutdown`
+	/// never returns while a batch is still in flight.
+	pub(crate) async fn drain(&self) {
+		let mut in_flight = self.in_flight.lock().await;
+		while in_flight.join_next().await.is_some() {}
+	}
+}
+
+impl Debug for ConcurrencyLimiter {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ConcurrencyLimiter").finish_non_exhaustive()
+	}
+}
+
+/// A cheap, dependency-free jitter source: we don't need cryptographic randomness here, just
+/// enough spread to avoid every exporter in a fleet retrying in lockstep.
+fn jitter(max: Duration) -> Duration {
+	if max.is_zero() {
+		return Duration::ZERO;
+	}
+	let nanos = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.subsec_nanos())
+		.unwrap_or(0) as u128;
+	Duration::from_nanos((nanos % max.as_nanos().max(1)) as u64)
+}
+
+/// Best-effort detection of retryable export failures from the error's rendered message: the
+/// OTLP exporter error types don't expose a structured status code we can match on here, so we
+/// look for the gRPC/HTTP statuses and connectivity failures that a transient collector
+/// restart produces.
+fn is_retryable(message: &str) -> bool {
+	const NEEDLES: &[&str] = &[
+		"UNAVAILABLE",
+		"RESOURCE_EXHAUSTED",
+		"DEADLINE_EXCEEDED",
+		"connection refused",
+		"timed out",
+		"timeout",
+		"429",
+		"502",
+		"503",
+		"504",
+	];
+	NEEDLES.iter().any(|needle| message.contains(needle))
+}
+
+/// Parses a `Retry-After: <seconds>` hint out of an error message, if the transport surfaced
+/// one. Collectors that return 429/503 under load commonly set this.
+fn retry_after(message: &str) -> Option<Duration> {
+	let (_, after) = message.split_once("Retry-After:")?;
+	let digits: String = after
+		.trim_start()
+		.chars()
+		.take_while(|c| c.is_ascii_digit())
+		.collect();
+	Some(Duration::from_secs(digits.parse().ok()?))
+}
+
+/// Retries `op` with exponential backoff and jitter until it succeeds, returns a non-retryable
+/// error, exhausts `policy.max_retries`, or exceeds `policy.max_elapsed`. Honors a `Retry-After`
+/// hint found in a retryable error's message in place of the computed backoff.
+pub(crate) async fn with_retry<T, F, Fut>(policy: &RetryPolicy, name: &str, mut op: F) -> OTelSdkResult
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<T, OTelSdkError>>,
+{
+	let start = SystemTime::now();
+	let mut attempt = 0;
+	loop {
+		match op().await {
+			Ok(_) => return Ok(()),
+			Err(err) => {
+				let message = err.to_string();
+				let elapsed = start.elapsed().unwrap_or(Duration::ZERO);
+				if !is_retryable(&message) || attempt >= policy.max_retries || elapsed >= policy.max_elapsed {
+					return Err(err);
+				}
+				let backoff = retry_after(&message).unwrap_or_else(|| policy.backoff_for(attempt));
+				warn!(
+					"{name} export failed (attempt {}/{}): {message}; retrying in {backoff:?}",
+					attempt + 1,
+					policy.max_retries
+				);
+				tokio::time::sleep(backoff).await;
+				attempt += 1;
+			}
+		}
+	}
+}
+
+/// Wraps a built [`LogExporter`] so that transient failures (collector restarts, `UNAVAILABLE`,
+/// `429`/`502`/`503`/`504`) are retried per [`RetryPolicy`] instead of dropping the batch.
+#[derive(Debug)]
+pub(crate) struct RetryingLogExporter {
+	inner: LogExporter,
+	policy: RetryPolicy,
+	limiter: ConcurrencyLimiter,
+}
+impl RetryingLogExporter {
+	pub(crate) fn new(inner: LogExporter, policy: RetryPolicy, max_concurrent_exports: u32) -> Self {
+		Self {
+			inner,
+			policy,
+			limiter: ConcurrencyLimiter::new(max_concurrent_exports),
+		}
+	}
+}
+impl LogExporterTrait for RetryingLogExporter {
+	async fn export(&self, batch: LogBatch<'_>) -> OTelSdkResult {
+		self.limiter
+			.gated(with_retry(&self.policy, "logs", || self.inner.export(batch)))
+			.await
+	}
+	fn shutdown(&mut self) -> OTelSdkResult {
+		self.inner.shutdown()
+	}
+}
+
+/// Wraps a built [`SpanExporter`]; see [`RetryingLogExporter`].
+#[derive(Debug)]
+pub(crate) struct RetryingSpanExporter {
+	inner: SpanExporter,
+	policy: RetryPolicy,
+	limiter: ConcurrencyLimiter,
+}
+impl RetryingSpanExporter {
+	pub(crate) fn new(inner: SpanExporter, policy: RetryPolicy, max_concurrent_exports: u32) -> Self {
+		Self {
+			inner,
+			policy,
+			limiter: ConcurrencyLimiter::new(max_concurrent_exports),
+		}
+	}
+}
+impl SpanExporterTrait for RetryingSpanExporter {
+	async fn export(&mut self, batch: Vec<SpanData>) -> OTelSdkResult {
+		// `SpanExporter::export` takes `&mut self`, so a single instance can't have two calls in
+		// flight at once; clone it (cheap — it just wraps a client handle) and hand the clone to
+		// an independent task instead of awaiting the request here, so the next `export` call
+		// isn't serialized behind this one.
+		let mut inner = self.inner.clone();
+		let policy = self.policy;
+		self.limiter
+			.spawn("traces", async move {
+				with_retry(&policy, "traces", || inner.export(batch.clone())).await
+			})
+			.await;
+		Ok(())
+	}
+	fn shutdown(&mut self) -> OTelSdkResult {
+		// `shutdown` is synchronous, but draining in-flight spawned exports requires awaiting.
+		// `Handle::current().block_on(...)` would reenter whatever runtime called us - which
+		// panics ("Cannot start a runtime from within a runtime") when shutdown runs from inside
+		// an already-async context on a current-thread runtime, exactly where a tracer
+		// provider's shutdown hook commonly runs. Drive the drain from a dedicated OS thread
+		// with its own runtime instead, so there's never a runtime to reenter.
+		let limiter = &self.limiter;
+		let drained = std::thread::scope(|scope| {
+			scope
+				.spawn(move || {
+					let rt = tokio::runtime::Builder::new_current_thread().build().map_err(|e| {
+						OTelSdkError::InternalFailure(format!(
+							"failed to start shutdown drain runtime: {e}"
+						))
+					})?;
+					rt.block_on(limiter.drain());
+					Ok(())
+				})
+				.join()
+		});
+		match drained {
+			Ok(result) => result?,
+			Err(_) => {
+				return Err(OTelSdkError::InternalFailure(
+					"shutdown drain thread panicked".to_owned(),
+				))
+			}
+		}
+		self.inner.shutdown()
+	}
+	fn set_resource(&mut self, resource: &Resource) {
+		self.inner.set_resource(resource);
+	}
+}
+
+/// Wraps a built [`MetricExporter`]; see [`RetryingLogExporter`]. Metrics have no batch-clone
+/// concern since `PushMetricExporter::export` already takes `&ResourceMetrics` by reference.
+#[derive(Debug)]
+pub(crate) struct RetryingMetricExporter {
+	inner: MetricExporter,
+	policy: RetryPolicy,
+	limiter: ConcurrencyLimiter,
+}
+impl RetryingMetricExporter {
+	pub(crate) fn new(inner: MetricExporter, policy: RetryPolicy, max_concurrent_exports: u32) -> Self {
+		Self {
+			inner,
+			policy,
+			limiter: ConcurrencyLimiter::new(max_concurrent_exports),
+		}
+	}
+}
+impl PushMetricExporter for RetryingMetricExporter {
+	async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+		self.limiter
+			.gated(with_retry(&self.policy, "metrics", || self.inner.export(metrics)))
+			.await
+	}
+	async fn force_flush(&self) -> OTelSdkResult {
+		self.inner.force_flush().await
+	}
+	fn shutdown(&self) -> OTelSdkResult {
+		self.inner.shutdown()
+	}
+	fn temporality(&self) -> opentelemetry_sdk::metrics::Temporality {
+		self.inner.temporality()
+	}
+}